@@ -0,0 +1,214 @@
+//! Wrappers of binutils programs to inspect the kernel or harness ELF.
+//!
+//! The programs matching the target architecture should be used, and the ELF should
+//! contain DWARF information for source locations.
+
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use log::debug;
+
+/// External programs to inspect the kernel ELF.
+#[derive(Debug, Clone)]
+pub struct Binutils {
+    objdump: String,
+    addr2line: String,
+    nm: String,
+}
+
+impl Binutils {
+    /// Use the programs with the prefix, e.g. `riscv64-unknown-elf-`.
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            objdump: format!("{prefix}objdump"),
+            addr2line: format!("{prefix}addr2line"),
+            nm: format!("{prefix}nm"),
+        }
+    }
+
+    /// Addresses of all instructions in the ELF, in ascending order.
+    pub fn instructions(&self, elf: &Path) -> Vec<u64> {
+        let output = run(
+            Command::new(&self.objdump)
+                .args(["-d", "--no-show-raw-insn"])
+                .arg(elf),
+            None,
+        );
+        let mut addresses: Vec<u64> = output
+            .lines()
+            .filter_map(|line| {
+                // Instructions are listed as `    80200000:\tauipc\tsp,0x1e`
+                let (addr, rest) = line.trim_start().split_once(':')?;
+                if !rest.starts_with('\t') {
+                    return None;
+                }
+                u64::from_str_radix(addr, 16).ok()
+            })
+            .collect();
+        addresses.sort_unstable();
+        addresses.dedup();
+        addresses
+    }
+
+    /// Function, file and line of each address.
+    pub fn symbolize(&self, elf: &Path, addresses: &[u64]) -> Vec<Location> {
+        let input: String = addresses
+            .iter()
+            .map(|addr| format!("{addr:#x}\n"))
+            .collect();
+        let output = run(
+            Command::new(&self.addr2line)
+                .args(["-f", "-C", "-e"])
+                .arg(elf),
+            Some(&input),
+        );
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines.len(),
+            addresses.len() * 2,
+            "Unexpected output of {}",
+            self.addr2line
+        );
+        lines
+            .chunks(2)
+            .map(|pair| Location::parse(pair[0], pair[1]))
+            .collect()
+    }
+
+    /// Defined functions in the ELF, with their source files if known.
+    pub fn functions(&self, elf: &Path) -> Vec<Symbol> {
+        let output = run(
+            Command::new(&self.nm)
+                .args(["-C", "-S", "-l", "--defined-only"])
+                .arg(elf),
+            None,
+        );
+        output.lines().filter_map(Symbol::parse).collect()
+    }
+
+    /// Address of the defined symbol with the exact name, e.g. a static variable.
+    pub fn symbol(&self, elf: &Path, name: &str) -> Option<u64> {
+        let output = run(Command::new(&self.nm).arg("--defined-only").arg(elf), None);
+        output.lines().find_map(|line| {
+            // Symbols are listed as `80210000 B FLICKER_TRACE`
+            let mut fields = line.split_whitespace();
+            let addr = fields.next()?;
+            if fields.nth(1)? != name {
+                return None;
+            }
+            u64::from_str_radix(addr, 16).ok()
+        })
+    }
+
+    /// Allocated sections in the ELF.
+    pub fn sections(&self, elf: &Path) -> Vec<Section> {
+        let output = run(Command::new(&self.objdump).arg("-h").arg(elf), None);
+        output
+            .lines()
+            .filter_map(|line| {
+                // Sections are listed as `  0 .text  0001a2c4  0000000080200000  ...`
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.len() < 4 || fields[0].parse::<usize>().is_err() {
+                    return None;
+                }
+                Some(Section {
+                    name: fields[1].to_string(),
+                    size: u64::from_str_radix(fields[2], 16).ok()?,
+                    addr: u64::from_str_radix(fields[3], 16).ok()?,
+                })
+            })
+            .filter(|section| section.addr != 0 && section.size != 0)
+            .collect()
+    }
+}
+
+/// Run the command, feeding the input to stdin, and return its stdout.
+fn run(command: &mut Command, input: Option<&str>) -> String {
+    debug!("[run] Running {command:?}");
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|e| panic!("Failed to run {command:?}: {e}"));
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.unwrap_or_default().to_string();
+    // Write in another thread, in case the pipe is full before the output is read
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap().unwrap();
+    assert!(
+        output.status.success(),
+        "{command:?} failed with {}",
+        output.status
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Source location of an instruction.
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub function: String,
+    pub file: PathBuf,
+    /// Line number, 0 if unknown
+    pub line: u32,
+}
+
+impl Location {
+    /// Parse the output of `addr2line -f`, e.g. `main` and `/path/main.rs:42 (discriminator 1)`.
+    fn parse(function: &str, position: &str) -> Self {
+        let position = position.split(" (").next().unwrap();
+        let (file, line) = position.rsplit_once(':').unwrap_or((position, "0"));
+        Self {
+            function: function.to_string(),
+            file: PathBuf::from(file),
+            line: line.parse().unwrap_or(0),
+        }
+    }
+}
+
+/// Function symbol in the ELF.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u64,
+    pub size: u64,
+    /// Source file defining the function, if known
+    pub file: Option<PathBuf>,
+}
+
+impl Symbol {
+    /// Parse the output of `nm -S -l`, e.g. `80200000 00000010 T main\t/path/main.rs:42`.
+    /// Only functions with sizes are kept.
+    fn parse(line: &str) -> Option<Self> {
+        let (head, location) = match line.split_once('\t') {
+            Some((head, location)) => (head, Some(location)),
+            None => (line, None),
+        };
+        // Demangled names may contain spaces
+        let mut fields = head.splitn(4, ' ');
+        let addr = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let size = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let kind = fields.next()?;
+        if !matches!(kind, "T" | "t" | "W" | "w") {
+            return None;
+        }
+        let file = location
+            .and_then(|location| location.rsplit_once(':'))
+            .map(|(file, _)| PathBuf::from(file));
+        Some(Self {
+            name: fields.next()?.to_string(),
+            addr,
+            size,
+            file,
+        })
+    }
+}
+
+/// Section in the ELF.
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub name: String,
+    pub addr: u64,
+    pub size: u64,
+}
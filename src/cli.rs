@@ -1,8 +1,13 @@
 //! Command line interface for flicker
 
+use std::ops::Range;
 use std::path::PathBuf;
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+#[cfg(not(feature = "bytes"))]
+use flicker::mutator::SyscallMutatorKind;
+
+use crate::filter::parse_range;
 
 #[derive(Parser)]
 #[command(version, about)]
@@ -17,16 +22,34 @@ pub enum Commands {
     Fuzz(FuzzOption),
     /// Reproduce a crash
     Reproduce(ReproduceOption),
+    /// Generate a source-level coverage report of a corpus
+    Coverage(CoverageOption),
+    /// Export a testcase as a standalone C or Rust program
+    #[cfg(not(feature = "bytes"))]
+    Export(ExportOption),
 }
 
 /// Fuzzing options
 #[derive(Args)]
 #[clap(trailing_var_arg = true)]
 pub struct FuzzOption {
-    /// Time limit for each run of the target
+    /// Time limit for each run of the target, or the max one if adaptive
     #[arg(short, long, default_value_t = 3)]
     pub timeout: u64,
 
+    /// Calibrate the time limit as this multiple of the average execution time of the
+    /// corpus, fixed if 0
+    #[arg(long, default_value_t = 0.0)]
+    pub timeout_multiplier: f64,
+
+    /// Min time limit in milliseconds if adaptive
+    #[arg(long, default_value_t = 100)]
+    pub min_timeout_ms: u64,
+
+    /// Longer time limit to verify that a timeout is a hang
+    #[arg(long, default_value_t = 10)]
+    pub hang_timeout: u64,
+
     /// Broker port
     #[arg(short, long, default_value_t = 1337)]
     pub port: u16,
@@ -47,21 +70,129 @@ pub struct FuzzOption {
     #[arg(long, default_value = "./crashes")]
     pub crash: PathBuf,
 
-    /// Path to the description file
+    /// Path to the directory of hangs
+    #[arg(long, default_value = "./hangs")]
+    pub hangs: PathBuf,
+
+    /// Path to the directory of crashes and hangs failing to reproduce
+    #[arg(long, default_value = "./flaky")]
+    pub flaky: PathBuf,
+
+    /// Times to re-execute crashes and hangs before reporting, never if 0
+    #[arg(long, default_value_t = 3)]
+    pub verify_runs: u64,
+
+    /// Min number of inputs in the corpus at start, generated if not enough are loaded
+    #[arg(long, default_value_t = 32)]
+    pub init_count: usize,
+
+    /// Probability to generate a brand-new input in each round of fuzzing
+    #[arg(long, default_value_t = 0.01)]
+    pub generate_prob: f64,
+
+    /// Policy to choose testcases from the corpus
+    #[arg(long, value_enum, default_value_t = SchedulerKind::Queue)]
+    pub scheduler: SchedulerKind,
+
+    /// Power schedule assigning mutation energy to testcases, unused by the `queue` scheduler
+    #[arg(long, value_enum, default_value_t = PowerScheduleKind::Fast)]
+    pub power_schedule: PowerScheduleKind,
+
+    /// Restore a full QEMU snapshot every N executions instead of a fast one, slower but
+    /// avoids leaking kernel state between executions. Requires a disk image supporting
+    /// snapshots (e.g. qcow2). Never if 0
+    #[arg(long, default_value_t = 0)]
+    pub full_snapshot_interval: u64,
+
+    /// Path to the builtin description file, use the embedded one if not specified
+    #[cfg(not(feature = "bytes"))]
+    #[arg(long)]
+    pub builtin: Option<PathBuf>,
+
+    /// Paths to the description files or directories, can be specified multiple times
+    #[cfg(not(feature = "bytes"))]
+    #[arg(long, required = true)]
+    pub desc: Vec<PathBuf>,
+
+    /// Paths to the constants files or directories, can be specified multiple times
     #[cfg(not(feature = "bytes"))]
+    #[arg(long, required = true)]
+    pub r#const: Vec<PathBuf>,
+
+    /// Paths to the dictionary files in AFL format, can be specified multiple times
     #[arg(long)]
-    pub desc: PathBuf,
+    pub dict: Vec<PathBuf>,
 
-    /// Path to the constants file
+    /// Path to the description of files on the guest, used to generate filenames
     #[cfg(not(feature = "bytes"))]
     #[arg(long)]
-    pub r#const: PathBuf,
+    pub file_tree: Option<PathBuf>,
+
+    /// Only instrument code in the address range for coverage, e.g. `0x80200000-0x80400000`,
+    /// can be specified multiple times
+    #[arg(long, value_parser = parse_range)]
+    pub cover_range: Vec<Range<u64>>,
+
+    /// Only instrument functions whose names contain the pattern, e.g. `fs::`,
+    /// can be specified multiple times
+    #[arg(long)]
+    pub cover_symbol: Vec<String>,
+
+    /// Only instrument code in the ELF section, can be specified multiple times
+    #[arg(long)]
+    pub cover_section: Vec<String>,
+
+    /// Only instrument functions defined in the source directory or file,
+    /// e.g. `kernel/Alien/kernel/src/fs`, can be specified multiple times
+    #[arg(long)]
+    pub cover_source: Vec<PathBuf>,
+
+    /// Path to the kernel ELF with debug info, required by `--cover-symbol`,
+    /// `--cover-section` and `--cover-source`
+    #[arg(long)]
+    pub kernel: Option<PathBuf>,
+
+    /// Prefix of binutils programs for the target architecture, e.g. `riscv64-unknown-elf-`
+    #[arg(long, default_value = "")]
+    pub binutils_prefix: String,
 
     /// Max number of calls per run
     #[cfg(not(feature = "bytes"))]
     #[arg(long, default_value = "30")]
     pub max_calls: usize,
 
+    /// Enabled syscall mutators, separated by commas
+    #[cfg(not(feature = "bytes"))]
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "splice,insert,rand,remove,swap,duplicate,move"
+    )]
+    pub mutators: Vec<SyscallMutatorKind>,
+
+    /// Choose syscall mutators uniformly, instead of adaptively by their success rates
+    #[cfg(not(feature = "bytes"))]
+    #[arg(long)]
+    pub uniform_mutators: bool,
+
+    /// Path to the directory to dump per-syscall statistics of each client periodically
+    #[cfg(not(feature = "bytes"))]
+    #[arg(long)]
+    pub syscall_stats: Option<PathBuf>,
+
+    /// Path to the file to append the progress of the campaign periodically,
+    /// in CSV if ending with `.csv`, otherwise in JSON lines
+    #[arg(long)]
+    pub stats_file: Option<PathBuf>,
+
+    /// Interval in seconds to write the progress into the stats file
+    #[arg(long, default_value_t = 60)]
+    pub stats_interval: u64,
+
+    /// Show the progress in a terminal UI, instead of printing lines of each event
+    #[arg(long)]
+    pub tui: bool,
+
     /// Max size of input
     #[cfg(feature = "bytes")]
     #[arg(long, default_value = "4096")]
@@ -72,6 +203,31 @@ pub struct FuzzOption {
     pub args: Vec<String>,
 }
 
+/// Policies to choose testcases from the corpus
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SchedulerKind {
+    /// In order, one mutation round each
+    Queue,
+    /// In order, with mutation energy from the power schedule
+    Power,
+    /// Randomly, weighted by the power schedule
+    Weighted,
+    /// Like `weighted`, but favoring testcases covering rare edges or rare syscalls
+    #[cfg(not(feature = "bytes"))]
+    Rare,
+}
+
+/// Power schedules, see [`libafl::schedulers::powersched::PowerSchedule`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PowerScheduleKind {
+    Explore,
+    Exploit,
+    Fast,
+    Coe,
+    Lin,
+    Quad,
+}
+
 /// Reproduction options
 #[derive(Args)]
 pub struct ReproduceOption {
@@ -82,11 +238,112 @@ pub struct ReproduceOption {
     #[arg(short, long, default_value_t = 3)]
     pub timeout: u64,
 
+    /// Path to the harness ELF, to trace the return value of each call from the static
+    /// buffer in it
+    #[cfg(not(feature = "bytes"))]
+    #[arg(long)]
+    pub trace: Option<PathBuf>,
+
+    /// Name of the static buffer in the harness with the return values of calls
+    #[cfg(not(feature = "bytes"))]
+    #[arg(long, default_value = "FLICKER_TRACE")]
+    pub trace_symbol: String,
+
+    /// Prefix of binutils programs for the target architecture, e.g. `riscv64-unknown-elf-`
+    #[cfg(not(feature = "bytes"))]
+    #[arg(long, default_value = "")]
+    pub binutils_prefix: String,
+
+    /// Path to the builtin description file, use the embedded one if not specified
+    #[cfg(not(feature = "bytes"))]
+    #[arg(long)]
+    pub builtin: Option<PathBuf>,
+
+    /// Paths to the description files or directories, to show the names of syscalls
+    /// when tracing
+    #[cfg(not(feature = "bytes"))]
+    #[arg(long)]
+    pub desc: Vec<PathBuf>,
+
+    /// Paths to the constants files or directories
+    #[cfg(not(feature = "bytes"))]
+    #[arg(long)]
+    pub r#const: Vec<PathBuf>,
+
     /// Arguments passed to Qemu
     #[arg(num_args = 0.., allow_hyphen_values = true)]
     pub args: Vec<String>,
 }
 
+/// Coverage report options
+#[derive(Args)]
+#[clap(trailing_var_arg = true)]
+pub struct CoverageOption {
+    /// Paths to the inputs or directories of inputs to replay, can be specified multiple times
+    #[arg(long, default_value = "./corpus/gen")]
+    pub corpus: Vec<PathBuf>,
+
+    /// Path to the kernel ELF with debug info
+    #[arg(long)]
+    pub kernel: PathBuf,
+
+    /// Path to the directory of kernel sources, files outside are excluded from the report
+    #[arg(long, default_value = "./kernel")]
+    pub source: PathBuf,
+
+    /// Path to the directory of the report
+    #[arg(long, default_value = "./coverage")]
+    pub output: PathBuf,
+
+    /// Prefix of binutils programs for the target architecture, e.g. `riscv64-unknown-elf-`
+    #[arg(long, default_value = "")]
+    pub binutils_prefix: String,
+
+    /// Time limit for each run of the target
+    #[arg(short, long, default_value_t = 3)]
+    pub timeout: u64,
+
+    /// Arguments passed to Qemu
+    #[arg(num_args = 0.., allow_hyphen_values = true)]
+    pub args: Vec<String>,
+}
+
+/// Export options
+#[cfg(not(feature = "bytes"))]
+#[derive(Args)]
+pub struct ExportOption {
+    /// Path to the testcase file
+    pub testcase: PathBuf,
+
+    /// Language of the program
+    #[arg(short, long, value_enum, default_value_t = LanguageKind::C)]
+    pub language: LanguageKind,
+
+    /// Path to the output file, print to stdout if not specified
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Path to the builtin description file, use the embedded one if not specified
+    #[arg(long)]
+    pub builtin: Option<PathBuf>,
+
+    /// Paths to the description files or directories, can be specified multiple times
+    #[arg(long, required = true)]
+    pub desc: Vec<PathBuf>,
+
+    /// Paths to the constants files or directories, can be specified multiple times
+    #[arg(long, required = true)]
+    pub r#const: Vec<PathBuf>,
+}
+
+/// Languages of exported programs
+#[cfg(not(feature = "bytes"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LanguageKind {
+    C,
+    Rust,
+}
+
 pub fn parse() -> Cli {
     Cli::parse()
 }
@@ -0,0 +1,140 @@
+//! Source-level coverage report of a corpus, by replaying its inputs.
+
+mod module;
+mod report;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[cfg(feature = "bytes")]
+use libafl::inputs::BytesInput;
+use libafl::{
+    corpus::NopCorpus, events::SimpleEventManager, inputs::Input, monitors::SimpleMonitor,
+    schedulers::QueueScheduler, state::StdState, StdFuzzer,
+};
+use libafl_bolts::{rands::StdRand, tuples::tuple_list};
+use libafl_qemu::{executor::QemuExecutor, Emulator};
+
+use crate::binutils::Binutils;
+use crate::cli::CoverageOption;
+use module::{BlockCoverage, BlockCoverageModule};
+use report::Report;
+
+#[cfg(not(feature = "bytes"))]
+use flicker::input::SyscallInput;
+
+pub fn coverage(opt: CoverageOption) {
+    let CoverageOption {
+        corpus,
+        kernel,
+        source,
+        output,
+        binutils_prefix,
+        timeout,
+        mut args,
+    } = opt;
+
+    let timeout = Duration::from_secs(timeout);
+
+    // Usually qemu is initialized with `env::args().collect()`,
+    // where the first argument is the path of the executable.
+    // Since we directly pass arguments into the fuzzer, we add
+    // an empty string as a placeholder.
+    args.insert(0, String::new());
+
+    #[cfg(not(feature = "bytes"))]
+    type Input = SyscallInput;
+    #[cfg(feature = "bytes")]
+    type Input = BytesInput;
+
+    // Initialize QEMU with the module recording executed blocks
+    let coverage = Arc::new(Mutex::new(BlockCoverage::default()));
+    let emulator = Emulator::builder()
+        .qemu_cli(args)
+        .modules(tuple_list!(BlockCoverageModule::new(coverage.clone())))
+        .build()
+        .expect("Failed to initialize QEMU");
+
+    let mut harness = |emu: &mut Emulator<_, _, _, _, _>, state: &mut _, input: &Input| unsafe {
+        emu.run(state, input).unwrap().try_into().unwrap()
+    };
+
+    // Empty feedback and objective
+    let mut feedback = ();
+    let mut objective = ();
+
+    let mut state = StdState::new(
+        StdRand::new(),
+        NopCorpus::new(),
+        NopCorpus::new(),
+        &mut feedback,
+        &mut objective,
+    )
+    .unwrap();
+
+    let scheduler = QueueScheduler::new();
+    let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
+
+    let monitor = SimpleMonitor::with_user_monitor(|s| {
+        println!("{s}");
+    });
+    let mut event_mgr = SimpleEventManager::new(monitor);
+
+    let mut executor = QemuExecutor::new(
+        emulator,
+        &mut harness,
+        tuple_list!(),
+        &mut fuzzer,
+        &mut state,
+        &mut event_mgr,
+        timeout,
+    )
+    .expect("Failed to create QemuExecutor");
+    executor.break_on_timeout();
+
+    // Replay all inputs
+    let files = list_inputs(&corpus);
+    for (i, file) in files.iter().enumerate() {
+        let input =
+            Input::from_file(file).unwrap_or_else(|e| panic!("Failed to load input {file:?}: {e}"));
+        match fuzzer.execute_input(&mut state, &mut executor, &mut event_mgr, &input) {
+            Ok(kind) => println!("[{}/{}] {file:?}: {kind:?}", i + 1, files.len()),
+            Err(e) => println!("[{}/{}] {file:?} failed: {e}", i + 1, files.len()),
+        }
+    }
+
+    // Symbolize and write the reports
+    let binutils = Binutils::new(&binutils_prefix);
+    let report = Report::new(&kernel, &source, &binutils, &coverage.lock().unwrap());
+    fs::create_dir_all(&output).unwrap_or_else(|e| panic!("Failed to create {output:?}: {e}"));
+    report.write_lcov(&output.join("lcov.info"));
+    report.write_functions(&output.join("functions.csv"));
+    report.write_html(&output);
+    println!("{}", report.summary());
+    println!("Coverage report written to {output:?}");
+}
+
+/// Input files in the given files or directories, skipping hidden files like metadata.
+fn list_inputs(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let entries = fs::read_dir(path)
+                .unwrap_or_else(|e| panic!("Failed to read directory {path:?}: {e}"));
+            let mut paths: Vec<PathBuf> = entries.map(|entry| entry.unwrap().path()).collect();
+            paths.sort();
+            files.extend(list_inputs(&paths));
+        } else if !is_hidden(path) {
+            files.push(path.clone());
+        }
+    }
+    files
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
@@ -0,0 +1,130 @@
+//! Emulator module recording executed basic blocks.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use libafl::inputs::UsesInput;
+use libafl_qemu::{
+    modules::{EmulatorModule, EmulatorModuleTuple, NopAddressFilter, NopPageFilter},
+    EmulatorModules, GuestAddr, GuestUsize, Hook,
+};
+
+/// Basic blocks executed, shared between the module and its owner.
+#[derive(Debug, Clone, Default)]
+pub struct BlockCoverage {
+    /// Start address, length and number of executions of each block, indexed by its id
+    blocks: Vec<(GuestAddr, GuestUsize, u64)>,
+    /// Id of the block starting at each address
+    ids: HashMap<GuestAddr, usize>,
+}
+
+impl BlockCoverage {
+    /// Executed blocks, as their start addresses, lengths and numbers of executions.
+    pub fn blocks(&self) -> impl Iterator<Item = (GuestAddr, GuestUsize, u64)> + '_ {
+        self.blocks.iter().copied().filter(|(_, _, hits)| *hits > 0)
+    }
+
+    fn id(&mut self, pc: GuestAddr) -> usize {
+        *self.ids.entry(pc).or_insert_with(|| {
+            self.blocks.push((pc, 0, 0));
+            self.blocks.len() - 1
+        })
+    }
+}
+
+/// Record the address, length and number of executions of each basic block.
+///
+/// Unlike the edge coverage modules, no hashing is involved, so the addresses can be
+/// symbolized later. It is slow, and only meant for replaying inputs.
+#[derive(Debug)]
+pub struct BlockCoverageModule {
+    coverage: Arc<Mutex<BlockCoverage>>,
+    address_filter: NopAddressFilter,
+    page_filter: NopPageFilter,
+}
+
+impl BlockCoverageModule {
+    pub fn new(coverage: Arc<Mutex<BlockCoverage>>) -> Self {
+        Self {
+            coverage,
+            address_filter: NopAddressFilter,
+            page_filter: NopPageFilter,
+        }
+    }
+}
+
+impl<S> EmulatorModule<S> for BlockCoverageModule
+where
+    S: Unpin + UsesInput,
+{
+    type ModuleAddressFilter = NopAddressFilter;
+    type ModulePageFilter = NopPageFilter;
+
+    fn first_exec<ET>(&mut self, emulator_modules: &mut EmulatorModules<ET, S>, _state: &mut S)
+    where
+        ET: EmulatorModuleTuple<S>,
+    {
+        emulator_modules.blocks(
+            Hook::Function(gen_block::<ET, S>),
+            Hook::Function(post_gen_block::<ET, S>),
+            Hook::Function(exec_block::<ET, S>),
+        );
+    }
+
+    fn address_filter(&self) -> &Self::ModuleAddressFilter {
+        &self.address_filter
+    }
+
+    fn address_filter_mut(&mut self) -> &mut Self::ModuleAddressFilter {
+        &mut self.address_filter
+    }
+
+    fn page_filter(&self) -> &Self::ModulePageFilter {
+        &self.page_filter
+    }
+
+    fn page_filter_mut(&mut self) -> &mut Self::ModulePageFilter {
+        &mut self.page_filter
+    }
+}
+
+fn gen_block<ET, S>(
+    emulator_modules: &mut EmulatorModules<ET, S>,
+    _state: Option<&mut S>,
+    pc: GuestAddr,
+) -> Option<u64>
+where
+    ET: EmulatorModuleTuple<S>,
+    S: Unpin + UsesInput,
+{
+    let module = emulator_modules.get::<BlockCoverageModule>()?;
+    let id = module.coverage.lock().unwrap().id(pc);
+    Some(id as u64)
+}
+
+fn post_gen_block<ET, S>(
+    emulator_modules: &mut EmulatorModules<ET, S>,
+    _state: Option<&mut S>,
+    pc: GuestAddr,
+    block_length: GuestUsize,
+) where
+    ET: EmulatorModuleTuple<S>,
+    S: Unpin + UsesInput,
+{
+    if let Some(module) = emulator_modules.get::<BlockCoverageModule>() {
+        let mut coverage = module.coverage.lock().unwrap();
+        let id = coverage.id(pc);
+        coverage.blocks[id].1 = block_length;
+    }
+}
+
+fn exec_block<ET, S>(emulator_modules: &mut EmulatorModules<ET, S>, _state: Option<&mut S>, id: u64)
+where
+    ET: EmulatorModuleTuple<S>,
+    S: Unpin + UsesInput,
+{
+    if let Some(module) = emulator_modules.get::<BlockCoverageModule>() {
+        let mut coverage = module.coverage.lock().unwrap();
+        coverage.blocks[id as usize].2 += 1;
+    }
+}
@@ -0,0 +1,305 @@
+//! Symbolization of covered addresses and generation of coverage reports.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use libafl_qemu::GuestAddr;
+
+use super::module::BlockCoverage;
+use crate::binutils::Binutils;
+
+/// Coverage of a source file.
+#[derive(Debug, Default)]
+struct FileCoverage {
+    /// Number of executions of each line with instructions
+    lines: BTreeMap<u32, u64>,
+}
+
+impl FileCoverage {
+    fn covered(&self) -> usize {
+        self.lines.values().filter(|hits| **hits > 0).count()
+    }
+}
+
+/// Coverage of a function.
+#[derive(Debug)]
+struct FunctionCoverage {
+    name: String,
+    file: PathBuf,
+    /// Number of executions of each line with instructions
+    lines: BTreeMap<u32, u64>,
+}
+
+impl FunctionCoverage {
+    fn first_line(&self) -> u32 {
+        self.lines.keys().next().copied().unwrap_or(0)
+    }
+
+    fn hits(&self) -> u64 {
+        self.lines.values().copied().max().unwrap_or(0)
+    }
+
+    fn covered(&self) -> usize {
+        self.lines.values().filter(|hits| **hits > 0).count()
+    }
+}
+
+/// Line and function coverage of the kernel sources.
+#[derive(Debug)]
+pub struct Report {
+    /// Root of the kernel sources
+    source: PathBuf,
+    /// Coverage of files, by paths relative to the source root
+    files: BTreeMap<PathBuf, FileCoverage>,
+    functions: Vec<FunctionCoverage>,
+}
+
+impl Report {
+    /// Map the covered blocks to the lines of the sources under `source`.
+    pub fn new(elf: &Path, source: &Path, binutils: &Binutils, coverage: &BlockCoverage) -> Self {
+        let instructions = binutils.instructions(elf);
+        println!("Found {} instructions in {elf:?}", instructions.len());
+
+        // Number of executions of each instruction
+        let mut hits = vec![0; instructions.len()];
+        for (pc, len, count) in coverage.blocks() {
+            let end_pc = pc + len as GuestAddr;
+            let start = instructions.partition_point(|addr| (*addr as GuestAddr) < pc);
+            let end = instructions.partition_point(|addr| (*addr as GuestAddr) < end_pc);
+            hits[start..end].iter_mut().for_each(|hit| *hit += count);
+        }
+
+        let source = source
+            .canonicalize()
+            .unwrap_or_else(|_| source.to_path_buf());
+        let mut files: BTreeMap<PathBuf, FileCoverage> = BTreeMap::new();
+        let mut functions: HashMap<(String, PathBuf), BTreeMap<u32, u64>> = HashMap::new();
+        for (location, hits) in binutils.symbolize(elf, &instructions).into_iter().zip(hits) {
+            if location.line == 0 {
+                continue;
+            }
+            let Some(file) = relative_to(&source, &location.file) else {
+                continue;
+            };
+            // Lines may have instructions in multiple blocks
+            let line = files
+                .entry(file.clone())
+                .or_default()
+                .lines
+                .entry(location.line)
+                .or_default();
+            *line = (*line).max(hits);
+            let line = functions
+                .entry((location.function, file))
+                .or_default()
+                .entry(location.line)
+                .or_default();
+            *line = (*line).max(hits);
+        }
+
+        let mut functions: Vec<FunctionCoverage> = functions
+            .into_iter()
+            .map(|((name, file), lines)| FunctionCoverage { name, file, lines })
+            .collect();
+        functions.sort_by(|a, b| (&a.file, a.first_line()).cmp(&(&b.file, b.first_line())));
+
+        Self {
+            source,
+            files,
+            functions,
+        }
+    }
+
+    /// Summary of the line and function coverage.
+    pub fn summary(&self) -> String {
+        let lines: usize = self.files.values().map(|file| file.lines.len()).sum();
+        let covered: usize = self.files.values().map(FileCoverage::covered).sum();
+        let functions = self.functions.iter().filter(|f| f.hits() > 0).count();
+        format!(
+            "Lines: {covered}/{lines} ({}), functions: {functions}/{} ({}), in {} files",
+            percent(covered, lines),
+            self.functions.len(),
+            percent(functions, self.functions.len()),
+            self.files.len()
+        )
+    }
+
+    /// Write the report in lcov tracefile format.
+    pub fn write_lcov(&self, path: &Path) {
+        let mut out = String::new();
+        for (file, coverage) in &self.files {
+            writeln!(out, "TN:").unwrap();
+            writeln!(out, "SF:{}", self.source.join(file).display()).unwrap();
+            let functions: Vec<_> = self.functions.iter().filter(|f| f.file == *file).collect();
+            for function in &functions {
+                writeln!(out, "FN:{},{}", function.first_line(), function.name).unwrap();
+            }
+            for function in &functions {
+                writeln!(out, "FNDA:{},{}", function.hits(), function.name).unwrap();
+            }
+            writeln!(out, "FNF:{}", functions.len()).unwrap();
+            writeln!(
+                out,
+                "FNH:{}",
+                functions.iter().filter(|f| f.hits() > 0).count()
+            )
+            .unwrap();
+            for (line, hits) in &coverage.lines {
+                writeln!(out, "DA:{line},{hits}").unwrap();
+            }
+            writeln!(out, "LF:{}", coverage.lines.len()).unwrap();
+            writeln!(out, "LH:{}", coverage.covered()).unwrap();
+            writeln!(out, "end_of_record").unwrap();
+        }
+        fs::write(path, out).unwrap_or_else(|e| panic!("Failed to write {path:?}: {e}"));
+    }
+
+    /// Write the coverage of each function in CSV format.
+    pub fn write_functions(&self, path: &Path) {
+        let mut out = String::from("function,file,line,covered_lines,lines,hits\n");
+        for function in &self.functions {
+            writeln!(
+                out,
+                "{},{},{},{},{},{}",
+                csv_field(&function.name),
+                csv_field(&function.file.display().to_string()),
+                function.first_line(),
+                function.covered(),
+                function.lines.len(),
+                function.hits()
+            )
+            .unwrap();
+        }
+        fs::write(path, out).unwrap_or_else(|e| panic!("Failed to write {path:?}: {e}"));
+    }
+
+    /// Write the HTML report into the directory, with an index page and a page for each file.
+    pub fn write_html(&self, dir: &Path) {
+        let files_dir = dir.join("files");
+        fs::create_dir_all(&files_dir)
+            .unwrap_or_else(|e| panic!("Failed to create {files_dir:?}: {e}"));
+
+        let mut index = html_header("Coverage");
+        writeln!(index, "<h1>Coverage</h1><p>{}</p>", escape(&self.summary())).unwrap();
+        index.push_str("<h2>Files</h2><table><tr><th>File</th><th>Lines</th><th>%</th></tr>");
+        for (id, (file, coverage)) in self.files.iter().enumerate() {
+            writeln!(
+                index,
+                "<tr><td><a href=\"files/{id}.html\">{}</a></td><td>{}/{}</td><td>{}</td></tr>",
+                escape(&file.display().to_string()),
+                coverage.covered(),
+                coverage.lines.len(),
+                percent(coverage.covered(), coverage.lines.len())
+            )
+            .unwrap();
+            self.write_html_file(&files_dir.join(format!("{id}.html")), file, coverage);
+        }
+        index.push_str("</table>");
+
+        index.push_str(
+            "<h2>Functions</h2><table><tr><th>Function</th><th>File</th>\
+             <th>Lines</th><th>%</th><th>Hits</th></tr>",
+        );
+        for function in &self.functions {
+            writeln!(
+                index,
+                "<tr><td>{}</td><td>{}:{}</td><td>{}/{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&function.name),
+                escape(&function.file.display().to_string()),
+                function.first_line(),
+                function.covered(),
+                function.lines.len(),
+                percent(function.covered(), function.lines.len()),
+                function.hits()
+            )
+            .unwrap();
+        }
+        index.push_str("</table></body></html>\n");
+
+        let path = dir.join("index.html");
+        fs::write(&path, index).unwrap_or_else(|e| panic!("Failed to write {path:?}: {e}"));
+    }
+
+    /// Write the source of the file, highlighting covered and uncovered lines.
+    fn write_html_file(&self, path: &Path, file: &Path, coverage: &FileCoverage) {
+        let name = file.display().to_string();
+        let mut page = html_header(&name);
+        writeln!(
+            page,
+            "<h1>{}</h1><p>Lines: {}/{} ({})</p><table class=\"source\">",
+            escape(&name),
+            coverage.covered(),
+            coverage.lines.len(),
+            percent(coverage.covered(), coverage.lines.len())
+        )
+        .unwrap();
+
+        // Only lines with instructions are listed if the source is not found
+        let text = fs::read_to_string(self.source.join(file)).ok();
+        let source_lines: Vec<(u32, &str)> = match &text {
+            Some(text) => (1..).zip(text.lines()).collect(),
+            None => coverage.lines.keys().map(|line| (*line, "")).collect(),
+        };
+        for (line, code) in source_lines {
+            let (class, hits) = match coverage.lines.get(&line) {
+                Some(0) => ("miss", "0".to_string()),
+                Some(hits) => ("hit", hits.to_string()),
+                None => ("", String::new()),
+            };
+            writeln!(
+                page,
+                "<tr class=\"{class}\"><td>{line}</td><td>{hits}</td><td><pre>{}</pre></td></tr>",
+                escape(code)
+            )
+            .unwrap();
+        }
+        page.push_str("</table></body></html>\n");
+        fs::write(path, page).unwrap_or_else(|e| panic!("Failed to write {path:?}: {e}"));
+    }
+}
+
+/// Path relative to the source root, if the file is under it.
+fn relative_to(source: &Path, file: &Path) -> Option<PathBuf> {
+    let file = if file.is_relative() {
+        source.join(file)
+    } else {
+        file.to_path_buf()
+    };
+    let file = file.canonicalize().unwrap_or(file);
+    file.strip_prefix(source).ok().map(Path::to_path_buf)
+}
+
+fn percent(part: usize, total: usize) -> String {
+    if total == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.1}%", part as f64 * 100.0 / total as f64)
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html_header(title: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{}</title><style>\
+         table {{ border-collapse: collapse; }} td, th {{ padding: 0 8px; text-align: left; }}\
+         pre {{ margin: 0; }} .hit {{ background: #cfc; }} .miss {{ background: #fcc; }}\
+         </style></head><body>\n",
+        escape(title)
+    )
+}
@@ -0,0 +1,250 @@
+//! Executor wrappers of the fuzzer.
+
+use std::time::Duration;
+
+use libafl::{
+    corpus::Corpus,
+    executors::{Executor, ExitKind, HasObservers},
+    inputs::UsesInput,
+    observers::ObserversTuple,
+    state::{HasCorpus, State, UsesState},
+    Error, HasMetadata,
+};
+use libafl_bolts::{tuples::RefIndexable, SerdeAny};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+/// Result of re-executing the last input that crashed or timed out, stored in the state.
+#[derive(Debug, Clone, Serialize, Deserialize, SerdeAny)]
+pub struct ReproducibilityMetadata {
+    /// Exit kind of the first execution
+    exit_kind: ExitKind,
+    /// Number of re-executions
+    runs: u64,
+    /// Number of re-executions with the same exit kind
+    reproduced: u64,
+}
+
+impl ReproducibilityMetadata {
+    pub fn exit_kind(&self) -> ExitKind {
+        self.exit_kind
+    }
+
+    pub fn runs(&self) -> u64 {
+        self.runs
+    }
+
+    pub fn reproduced(&self) -> u64 {
+        self.reproduced
+    }
+
+    /// Whether all re-executions reproduce the first one.
+    pub fn is_reproducible(&self) -> bool {
+        self.reproduced == self.runs
+    }
+
+    pub fn ratio(&self) -> f64 {
+        if self.runs == 0 {
+            1.0
+        } else {
+            self.reproduced as f64 / self.runs as f64
+        }
+    }
+}
+
+/// Timeouts of executions.
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    /// Max timeout of normal executions
+    max: Duration,
+    /// Min timeout of normal executions, if adaptive
+    min: Duration,
+    /// Multiple of the average execution time of the corpus as the timeout, fixed if 0
+    multiplier: f64,
+    /// Timeout to verify hangs
+    hang: Duration,
+}
+
+impl Timeouts {
+    /// Fixed timeout for normal executions, and a longer one to verify hangs.
+    pub fn new(max: Duration, hang: Duration) -> Self {
+        Self {
+            max,
+            min: max,
+            multiplier: 0.0,
+            hang: hang.max(max),
+        }
+    }
+
+    /// Calibrate the timeout as `multiplier` times the average execution time of the
+    /// corpus, in `[min, max]`.
+    pub fn adaptive(self, min: Duration, multiplier: f64) -> Self {
+        Self {
+            min: min.min(self.max),
+            multiplier,
+            ..self
+        }
+    }
+
+    fn is_adaptive(&self) -> bool {
+        self.multiplier > 0.0
+    }
+}
+
+/// Executor managing timeouts and verifying inputs that crash or time out.
+///
+/// The timeout may be calibrated from the execution times of the corpus. Inputs timing out
+/// are re-executed with a longer timeout, and only treated as hangs if it persists. Then
+/// inputs that crash or hang are re-executed for a number of times, recording how many times
+/// it is reproduced in [`ReproducibilityMetadata`].
+///
+/// The guest is restored to the snapshot after each execution, so every re-execution
+/// starts from the same state. Observers are reset before each re-execution, and reflect
+/// the last one afterwards.
+pub struct VerifyExecutor<E, F> {
+    inner: E,
+    /// Number of re-executions, disabled if 0
+    runs: u64,
+    timeouts: Timeouts,
+    /// Set the timeout of the inner executor
+    set_timeout: F,
+    /// Current timeout of normal executions
+    timeout: Duration,
+    /// Number of corpus entries when the timeout is calibrated
+    calibrated: usize,
+}
+
+impl<E, F> VerifyExecutor<E, F>
+where
+    F: FnMut(&mut E, Duration),
+{
+    pub fn new(mut inner: E, runs: u64, timeouts: Timeouts, mut set_timeout: F) -> Self {
+        set_timeout(&mut inner, timeouts.max);
+        Self {
+            inner,
+            runs,
+            timeouts,
+            set_timeout,
+            timeout: timeouts.max,
+            calibrated: 0,
+        }
+    }
+
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut E {
+        &mut self.inner
+    }
+
+    /// Recompute the timeout if the corpus has changed.
+    fn calibrate<S: HasCorpus>(&mut self, state: &S) -> Result<(), Error> {
+        let count = state.corpus().count();
+        if !self.timeouts.is_adaptive() || count == self.calibrated {
+            return Ok(());
+        }
+        self.calibrated = count;
+
+        let mut total = Duration::ZERO;
+        let mut timed = 0;
+        for id in state.corpus().ids() {
+            if let Some(time) = *state.corpus().get(id)?.borrow().exec_time() {
+                total += time;
+                timed += 1;
+            }
+        }
+        if timed == 0 {
+            return Ok(());
+        }
+
+        let timeout = (total / timed)
+            .mul_f64(self.timeouts.multiplier)
+            .clamp(self.timeouts.min, self.timeouts.max);
+        if timeout != self.timeout {
+            debug!("[VerifyExecutor::calibrate] Timeout set to {timeout:?}");
+            self.timeout = timeout;
+            (self.set_timeout)(&mut self.inner, timeout);
+        }
+        Ok(())
+    }
+}
+
+impl<E, F> UsesState for VerifyExecutor<E, F>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E, F, EM, Z> Executor<EM, Z> for VerifyExecutor<E, F>
+where
+    E: Executor<EM, Z> + HasObservers,
+    E::Observers: ObserversTuple<<E::State as UsesInput>::Input, E::State>,
+    E::State: State + HasMetadata + HasCorpus,
+    F: FnMut(&mut E, Duration),
+    EM: UsesState<State = E::State>,
+    Z: UsesState<State = E::State>,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut Self::State,
+        mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        self.calibrate(state)?;
+        // Metadata of the previous execution, if not taken by the feedback
+        state.remove_metadata::<ReproducibilityMetadata>();
+
+        let mut exit_kind = self.inner.run_target(fuzzer, state, mgr, input)?;
+        let hang_timeout = exit_kind == ExitKind::Timeout && self.timeout < self.timeouts.hang;
+        if hang_timeout {
+            // Check if it is a hang or merely a slow input
+            (self.set_timeout)(&mut self.inner, self.timeouts.hang);
+            self.inner.observers_mut().pre_exec_all(state, input)?;
+            exit_kind = self.inner.run_target(fuzzer, state, mgr, input)?;
+            debug!("[VerifyExecutor::run_target] Timed out, {exit_kind:?} with longer timeout");
+        }
+
+        if self.runs > 0 && matches!(exit_kind, ExitKind::Crash | ExitKind::Timeout) {
+            let mut reproduced = 0;
+            for _ in 0..self.runs {
+                self.inner.observers_mut().pre_exec_all(state, input)?;
+                if self.inner.run_target(fuzzer, state, mgr, input)? == exit_kind {
+                    reproduced += 1;
+                }
+            }
+            debug!(
+                "[VerifyExecutor::run_target] {exit_kind:?} reproduced {reproduced}/{} times",
+                self.runs
+            );
+
+            state.add_metadata(ReproducibilityMetadata {
+                exit_kind,
+                runs: self.runs,
+                reproduced,
+            });
+        }
+
+        if hang_timeout {
+            (self.set_timeout)(&mut self.inner, self.timeout);
+        }
+        Ok(exit_kind)
+    }
+}
+
+impl<E, F> HasObservers for VerifyExecutor<E, F>
+where
+    E: HasObservers,
+{
+    type Observers = E::Observers;
+
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        self.inner.observers()
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        self.inner.observers_mut()
+    }
+}
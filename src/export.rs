@@ -0,0 +1,48 @@
+//! Export of a testcase as a standalone program, to reproduce it without the fuzzer.
+
+use std::fs;
+
+use libafl::inputs::Input;
+
+use crate::cli::{ExportOption, LanguageKind};
+
+use flicker::{
+    input::SyscallInput,
+    parser::parse,
+    program::{
+        export::{export as export_program, Language},
+        metadata::SyscallMetadata,
+    },
+};
+
+pub fn export(opt: ExportOption) {
+    let ExportOption {
+        testcase,
+        language,
+        output,
+        builtin,
+        desc,
+        r#const,
+    } = opt;
+
+    let input = SyscallInput::from_file(&testcase)
+        .unwrap_or_else(|e| panic!("Failed to load input {testcase:?}: {e}"));
+    let metadata = SyscallMetadata::from_parsed(parse(builtin.as_deref(), &desc, &r#const));
+    let program = export_program(&input, &metadata, to_language(language))
+        .unwrap_or_else(|e| panic!("Failed to export {testcase:?}: {e}"));
+
+    match output {
+        Some(path) => {
+            fs::write(&path, program).unwrap_or_else(|e| panic!("Failed to write {path:?}: {e}"));
+            println!("Program written to {path:?}");
+        }
+        None => print!("{program}"),
+    }
+}
+
+fn to_language(kind: LanguageKind) -> Language {
+    match kind {
+        LanguageKind::C => Language::C,
+        LanguageKind::Rust => Language::Rust,
+    }
+}
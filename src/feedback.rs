@@ -0,0 +1,223 @@
+//! Feedbacks of the fuzzer.
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use libafl::{
+    corpus::{Corpus, OnDiskCorpus, Testcase},
+    executors::ExitKind,
+    feedbacks::{Feedback, MapIndexesMetadata, StateInitializer},
+    inputs::Input,
+    Error, HasMetadata,
+};
+use libafl_bolts::Named;
+use log::debug;
+
+use crate::executor::ReproducibilityMetadata;
+use crate::input::SyscallInput;
+use crate::stats::SyscallStats;
+
+/// Objective feedback accepting crashes only if they are reproducible according to
+/// [`ReproducibilityMetadata`], which is attached to the solutions. Reproducible timeouts,
+/// i.e. hangs, and flaky crashes or hangs are kept in separate corpora instead.
+///
+/// Inputs not verified, i.e. without the metadata, are treated as reproducible.
+pub struct ReproducibleFeedback<I> {
+    flaky: OnDiskCorpus<I>,
+    hangs: OnDiskCorpus<I>,
+    /// Metadata of the current execution, taken from the state
+    metadata: Option<ReproducibilityMetadata>,
+}
+
+impl<I: Input> ReproducibleFeedback<I> {
+    pub fn new(flaky_dir: &Path, hangs_dir: &Path) -> Self {
+        Self {
+            flaky: OnDiskCorpus::new(flaky_dir).expect("Failed to create flaky corpus"),
+            hangs: OnDiskCorpus::new(hangs_dir).expect("Failed to create hangs corpus"),
+            metadata: None,
+        }
+    }
+}
+
+impl<I> Named for ReproducibleFeedback<I> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ReproducibleFeedback");
+        &NAME
+    }
+}
+
+impl<I, S> StateInitializer<S> for ReproducibleFeedback<I> {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for ReproducibleFeedback<I>
+where
+    I: Input,
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        input: &I,
+        _observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        // Take the metadata out, so that it never leaks to later executions
+        self.metadata = state
+            .remove_metadata::<ReproducibilityMetadata>()
+            .map(|metadata| *metadata)
+            .filter(|metadata| metadata.exit_kind() == *exit_kind);
+        if !matches!(exit_kind, ExitKind::Crash | ExitKind::Timeout) {
+            return Ok(false);
+        }
+        let metadata = self.metadata.as_ref();
+        let reproducible = metadata.map_or(true, |metadata| metadata.is_reproducible());
+        if reproducible && *exit_kind == ExitKind::Crash {
+            return Ok(true);
+        }
+
+        let mut testcase = Testcase::new(input.clone());
+        match metadata {
+            Some(metadata) if !reproducible => {
+                debug!(
+                    "[ReproducibleFeedback::is_interesting] Flaky {exit_kind:?}, reproduced {}/{} times",
+                    metadata.reproduced(),
+                    metadata.runs()
+                );
+                testcase.add_metadata(metadata.clone());
+                self.flaky.add(testcase)?;
+            }
+            _ => {
+                debug!("[ReproducibleFeedback::is_interesting] Found a hang");
+                if let Some(metadata) = metadata {
+                    testcase.add_metadata(metadata.clone());
+                }
+                self.hangs.add(testcase)?;
+            }
+        }
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        if let Some(metadata) = self.metadata.take() {
+            testcase.add_metadata(metadata);
+        }
+        Ok(())
+    }
+}
+
+/// Feedback counting executions and solutions of syscalls in [`SyscallStats`], never
+/// interesting itself. It should be combined with the objective, which is evaluated for
+/// every execution, while the corpus feedback is not for solutions.
+#[derive(Debug, Default)]
+pub struct SyscallStatsFeedback;
+
+impl SyscallStatsFeedback {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for SyscallStatsFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("SyscallStatsFeedback");
+        &NAME
+    }
+}
+
+impl<S> StateInitializer<S> for SyscallStatsFeedback {}
+
+impl<EM, OT, S> Feedback<EM, SyscallInput, OT, S> for SyscallStatsFeedback
+where
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        input: &SyscallInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        state
+            .metadata_or_insert_with(SyscallStats::default)
+            .record_execution(input);
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<SyscallInput>,
+    ) -> Result<(), Error> {
+        if let Some(input) = testcase.input() {
+            state
+                .metadata_or_insert_with(SyscallStats::default)
+                .record_crash(input);
+        }
+        Ok(())
+    }
+}
+
+/// Feedback counting corpus entries and the new edges they find for syscalls in
+/// [`SyscallStats`], never interesting itself. It should be combined after the map
+/// feedback tracking indices, which attaches [`MapIndexesMetadata`] to the entries.
+#[derive(Debug, Default)]
+pub struct SyscallCoverageFeedback;
+
+impl SyscallCoverageFeedback {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for SyscallCoverageFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("SyscallCoverageFeedback");
+        &NAME
+    }
+}
+
+impl<S> StateInitializer<S> for SyscallCoverageFeedback {}
+
+impl<EM, OT, S> Feedback<EM, SyscallInput, OT, S> for SyscallCoverageFeedback
+where
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &SyscallInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<SyscallInput>,
+    ) -> Result<(), Error> {
+        let Some(input) = testcase.input() else {
+            return Ok(());
+        };
+        let edges = testcase
+            .metadata::<MapIndexesMetadata>()
+            .map_or(&[][..], |indexes| &indexes.list);
+        state
+            .metadata_or_insert_with(SyscallStats::default)
+            .record_corpus_entry(input, edges);
+        Ok(())
+    }
+}
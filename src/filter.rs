@@ -0,0 +1,147 @@
+//! Filters of the code instrumented for coverage.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use libafl_qemu::{modules::StdAddressFilter, GuestAddr};
+
+use crate::binutils::Binutils;
+
+/// Code to instrument, as the union of address ranges, functions, sections and sources.
+#[derive(Debug, Clone, Default)]
+pub struct CoverageFilter {
+    ranges: Vec<Range<u64>>,
+    /// Patterns contained in the names of functions
+    symbols: Vec<String>,
+    /// Names of sections
+    sections: Vec<String>,
+    /// Directories or files of sources
+    sources: Vec<PathBuf>,
+}
+
+impl CoverageFilter {
+    pub fn new(
+        ranges: Vec<Range<u64>>,
+        symbols: Vec<String>,
+        sections: Vec<String>,
+        sources: Vec<PathBuf>,
+    ) -> Self {
+        Self {
+            ranges,
+            symbols,
+            sections,
+            sources,
+        }
+    }
+
+    /// Whether all code is instrumented.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+            && self.symbols.is_empty()
+            && self.sections.is_empty()
+            && self.sources.is_empty()
+    }
+
+    /// Whether the kernel ELF is needed to resolve the filter.
+    fn needs_elf(&self) -> bool {
+        !self.symbols.is_empty() || !self.sections.is_empty() || !self.sources.is_empty()
+    }
+
+    /// Resolve the filter into sorted and merged address ranges, looking up the kernel ELF.
+    fn resolve(&self, kernel: Option<&Path>, binutils: &Binutils) -> Vec<Range<u64>> {
+        let mut ranges = self.ranges.clone();
+
+        if self.needs_elf() {
+            let kernel =
+                kernel.expect("Kernel ELF is required to filter by symbols, sections or sources");
+            if !self.sections.is_empty() {
+                ranges.extend(
+                    binutils
+                        .sections(kernel)
+                        .into_iter()
+                        .filter(|section| self.sections.contains(&section.name))
+                        .map(|section| section.addr..section.addr + section.size),
+                );
+            }
+            if !self.symbols.is_empty() || !self.sources.is_empty() {
+                let sources: Vec<PathBuf> = self
+                    .sources
+                    .iter()
+                    .map(|source| source.canonicalize().unwrap_or_else(|_| source.clone()))
+                    .collect();
+                ranges.extend(
+                    binutils
+                        .functions(kernel)
+                        .into_iter()
+                        .filter(|function| {
+                            self.symbols
+                                .iter()
+                                .any(|pattern| function.name.contains(pattern.as_str()))
+                                || function.file.as_ref().is_some_and(|file| {
+                                    sources.iter().any(|source| file.starts_with(source))
+                                })
+                        })
+                        .map(|function| function.addr..function.addr + function.size),
+                );
+            }
+        }
+
+        assert!(!ranges.is_empty(), "No code matches the coverage filter");
+        merge(ranges)
+    }
+
+    /// Address filter for the coverage module, instrumenting everything if empty.
+    pub fn address_filter(&self, kernel: Option<&Path>, binutils: &Binutils) -> StdAddressFilter {
+        if self.is_empty() {
+            return StdAddressFilter::default();
+        }
+        let ranges = self.resolve(kernel, binutils);
+        println!(
+            "Instrumenting {} bytes of code in {} ranges",
+            ranges
+                .iter()
+                .map(|range| range.end - range.start)
+                .sum::<u64>(),
+            ranges.len()
+        );
+        StdAddressFilter::allow_list(
+            ranges
+                .into_iter()
+                .map(|range| range.start as GuestAddr..range.end as GuestAddr)
+                .collect(),
+        )
+    }
+}
+
+/// Parse a range of addresses like `0x80200000-0x80400000`.
+pub fn parse_range(text: &str) -> Result<Range<u64>, String> {
+    let parse = |addr: &str| {
+        let addr = addr.trim();
+        let addr = addr
+            .strip_prefix("0x")
+            .or_else(|| addr.strip_prefix("0X"))
+            .unwrap_or(addr);
+        u64::from_str_radix(addr, 16).map_err(|e| format!("Invalid address {addr:?}: {e}"))
+    };
+    let (start, end) = text
+        .split_once('-')
+        .ok_or_else(|| format!("Expect a range like 0x1000-0x2000, got {text:?}"))?;
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start >= end {
+        return Err(format!("Empty range {text:?}"));
+    }
+    Ok(start..end)
+}
+
+/// Sort the ranges and merge overlapping or adjacent ones.
+fn merge(mut ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<u64>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
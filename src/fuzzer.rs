@@ -5,21 +5,31 @@ use std::time::Duration;
 use libafl::{
     corpus::{Corpus, InMemoryOnDiskCorpus, OnDiskCorpus},
     events::{EventConfig, Launcher},
-    feedback_or, feedback_or_fast,
+    executors::HasTimeout,
+    feedback_and_fast, feedback_or, feedback_or_fast,
     feedbacks::{CrashFeedback, MaxMapFeedback, TimeFeedback, TimeoutFeedback},
     fuzzer::{Fuzzer, StdFuzzer},
-    monitors::MultiMonitor,
-    mutators::StdScheduledMutator,
+    monitors::{tui::TuiMonitor, MultiMonitor},
     observers::{CanTrack, HitcountsMapObserver, TimeObserver, VariableMapObserver},
-    schedulers::{IndexesLenTimeMinimizerScheduler, QueueScheduler},
-    stages::{CalibrationStage, StdMutationalStage},
+    schedulers::{
+        powersched::PowerSchedule, IndexesLenTimeMinimizerScheduler, PowerQueueScheduler,
+        QueueScheduler, StdWeightedScheduler,
+    },
+    stages::{CalibrationStage, StdMutationalStage, StdPowerMutationalStage},
     state::{HasCorpus, HasMaxSize, StdState},
     Error,
 };
 #[cfg(feature = "bytes")]
-use libafl::{generators::RandBytesGenerator, inputs::BytesInput, mutators::havoc_mutations};
+use libafl::{
+    generators::RandBytesGenerator,
+    inputs::BytesInput,
+    mutators::{havoc_mutations, tokens_mutations, StdScheduledMutator},
+    HasMetadata,
+};
+#[cfg(feature = "bytes")]
+use libafl_bolts::tuples::Merge;
 use libafl_bolts::{
-    core_affinity::Cores,
+    core_affinity::{CoreId, Cores},
     current_nanos,
     ownedref::OwnedMutSlice,
     rands::StdRand,
@@ -29,16 +39,33 @@ use libafl_bolts::{
 use libafl_qemu::{executor::QemuExecutor, modules::StdEdgeCoverageClassicModule, Emulator};
 use libafl_targets::{edges_map_mut_ptr, EDGES_MAP_DEFAULT_SIZE, MAX_EDGES_FOUND};
 
+use flicker::{
+    executor::{Timeouts, VerifyExecutor},
+    feedback::ReproducibleFeedback,
+    monitor::StatsFileMonitor,
+    program::dictionary::load_tokens,
+    stages::GenerationStage,
+};
 #[cfg(not(feature = "bytes"))]
 use flicker::{
+    feedback::{SyscallCoverageFeedback, SyscallStatsFeedback},
     generator::SyscallGenerator,
     input::SyscallInput,
-    mutator::syscall_mutations,
+    mutator::SyscallScheduledMutator,
     parser::parse,
-    program::{context::Context, metadata::SyscallMetadata},
+    program::{
+        context::Context, dictionary::Dictionary, file_tree::FileTree, metadata::SyscallMetadata,
+    },
+    scheduler::RareTestcaseScore,
+    stages::{CorpusRarityStage, MutatorStatsStage, SyscallStatsStage},
 };
+#[cfg(not(feature = "bytes"))]
+use libafl::schedulers::WeightedScheduler;
 
-use crate::cli::FuzzOption;
+use crate::binutils::Binutils;
+use crate::cli::{FuzzOption, PowerScheduleKind, SchedulerKind};
+use crate::filter::CoverageFilter;
+use crate::snapshot::FlickerSnapshotManager;
 
 // /// Metadata for testcases for reproduction.
 // #[derive(Debug, Clone, Serialize, Deserialize, SerdeAny)]
@@ -54,23 +81,56 @@ use crate::cli::FuzzOption;
 pub fn fuzz(opt: FuzzOption) {
     let FuzzOption {
         timeout,
+        timeout_multiplier,
+        min_timeout_ms,
+        hang_timeout,
         port: broker_port,
         cores,
         init_corpus,
         gen_corpus,
         crash,
+        hangs,
+        flaky,
+        verify_runs,
+        init_count,
+        generate_prob,
+        scheduler,
+        power_schedule,
+        full_snapshot_interval,
+        #[cfg(not(feature = "bytes"))]
+        builtin,
         #[cfg(not(feature = "bytes"))]
         desc,
         #[cfg(not(feature = "bytes"))]
         r#const,
+        dict,
+        #[cfg(not(feature = "bytes"))]
+        file_tree,
+        cover_range,
+        cover_symbol,
+        cover_section,
+        cover_source,
+        kernel,
+        binutils_prefix,
         #[cfg(not(feature = "bytes"))]
         max_calls,
+        #[cfg(not(feature = "bytes"))]
+        mutators,
+        #[cfg(not(feature = "bytes"))]
+        uniform_mutators,
+        #[cfg(not(feature = "bytes"))]
+        syscall_stats,
+        stats_file,
+        stats_interval,
+        tui,
         #[cfg(feature = "bytes")]
         max_size,
         mut args,
     } = opt;
 
     let timeout = Duration::from_secs(timeout);
+    let timeouts = Timeouts::new(timeout, Duration::from_secs(hang_timeout))
+        .adaptive(Duration::from_millis(min_timeout_ms), timeout_multiplier);
     let cores = Cores::from_cmdline(&cores).unwrap();
     // TODO: Add cli options to testcases as metadata
     // let testcase_metadata = TestcaseMetadata {
@@ -85,12 +145,29 @@ pub fn fuzz(opt: FuzzOption) {
     // an empty string as a placeholder.
     args.insert(0, String::new());
 
+    let tokens = load_tokens(&dict);
+
+    #[cfg(not(feature = "bytes"))]
+    if let Some(dir) = &syscall_stats {
+        std::fs::create_dir_all(dir).unwrap_or_else(|e| panic!("Failed to create {dir:?}: {e}"));
+    }
+
+    // Resolve the code to instrument once, instead of in every client
+    let address_filter =
+        CoverageFilter::new(cover_range, cover_symbol, cover_section, cover_source)
+            .address_filter(kernel.as_deref(), &Binutils::new(&binutils_prefix));
+
     #[cfg(not(feature = "bytes"))]
-    let syscall_metadata = SyscallMetadata::from_parsed(parse(&desc, &r#const));
+    let syscall_metadata = SyscallMetadata::from_parsed(parse(builtin.as_deref(), &desc, &r#const))
+        .with_dictionary(Dictionary::new(&tokens))
+        .with_file_tree(
+            file_tree.map_or_else(FileTree::default, |path| FileTree::from_file(&path)),
+        );
 
-    let mut run_client = |state: Option<_>, mut mgr, _core_id| {
+    let mut run_client = |state: Option<_>, mut mgr, core_id: CoreId| {
         // Choose modules
         let modules = tuple_list!(StdEdgeCoverageClassicModule::builder()
+            .address_filter(address_filter.clone())
             .build()
             .expect("Failed to create coverage module"));
 
@@ -98,6 +175,7 @@ pub fn fuzz(opt: FuzzOption) {
         let emulator = Emulator::builder()
             .qemu_cli(args.clone())
             .modules(modules)
+            .snapshot_manager(FlickerSnapshotManager::new(full_snapshot_interval))
             .build()
             .expect("Failed to initialize QEMU");
 
@@ -129,6 +207,16 @@ pub fn fuzz(opt: FuzzOption) {
 
         // Feedback to rate the interestingness of an input
         // This one is composed by two Feedbacks in OR
+        #[cfg(not(feature = "bytes"))]
+        let mut feedback = feedback_or!(
+            // New maximization map feedback linked to the edges observer and the feedback state
+            MaxMapFeedback::new(&edges_observer),
+            // Time feedback, this one does not need a feedback state
+            TimeFeedback::new(&time_observer),
+            // Count corpus entries and new edges of syscalls, after the indices are tracked
+            SyscallCoverageFeedback::new()
+        );
+        #[cfg(feature = "bytes")]
         let mut feedback = feedback_or!(
             // New maximization map feedback linked to the edges observer and the feedback state
             MaxMapFeedback::new(&edges_observer),
@@ -136,8 +224,22 @@ pub fn fuzz(opt: FuzzOption) {
             TimeFeedback::new(&time_observer)
         );
 
-        // A feedback to choose if an input is a solution or not
-        let mut objective = feedback_or_fast!(CrashFeedback::new(), TimeoutFeedback::new());
+        // A feedback to choose if an input is a solution or not,
+        // keeping only reproducible crashes, while hangs are stored separately
+        #[cfg(not(feature = "bytes"))]
+        let mut objective = feedback_or!(
+            // Count executions and crashes of syscalls, evaluated for every execution
+            SyscallStatsFeedback::new(),
+            feedback_and_fast!(
+                feedback_or_fast!(CrashFeedback::new(), TimeoutFeedback::new()),
+                ReproducibleFeedback::new(&flaky, &hangs)
+            )
+        );
+        #[cfg(feature = "bytes")]
+        let mut objective = feedback_and_fast!(
+            feedback_or_fast!(CrashFeedback::new(), TimeoutFeedback::new()),
+            ReproducibleFeedback::new(&flaky, &hangs)
+        );
 
         // If not restarting, create a State from scratch
         let mut state = state.unwrap_or_else(|| {
@@ -160,101 +262,206 @@ pub fn fuzz(opt: FuzzOption) {
             new_state.set_max_size(max_calls);
             #[cfg(feature = "bytes")]
             new_state.set_max_size(max_size);
+            #[cfg(feature = "bytes")]
+            new_state.add_metadata(tokens.clone());
             new_state
         });
 
-        // A minimization+queue policy to get testcasess from the corpus
-        let scheduler =
-            IndexesLenTimeMinimizerScheduler::new(&edges_observer, QueueScheduler::new());
-
-        // A fuzzer with feedbacks and a corpus scheduler
-        let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
-
-        // Setup a syscall mutator with a mutational stage
+        // Setup a syscall mutator
         #[cfg(not(feature = "bytes"))]
-        let mutator = StdScheduledMutator::new(syscall_mutations(syscall_metadata.clone()));
+        let mutator =
+            SyscallScheduledMutator::new(syscall_metadata.clone(), &mutators, !uniform_mutators);
         #[cfg(feature = "bytes")]
-        let mutator = StdScheduledMutator::new(havoc_mutations());
+        let mutator = StdScheduledMutator::new(havoc_mutations().merge(tokens_mutations()));
         let calibration_feedback = MaxMapFeedback::new(&edges_observer);
-        let mut stages = tuple_list!(
-            StdMutationalStage::new(mutator),
-            CalibrationStage::new(&calibration_feedback)
-        );
+        let schedule = to_power_schedule(power_schedule);
+
+        // Generator of brand-new inputs, for the initial corpus and during fuzzing
+        #[cfg(not(feature = "bytes"))]
+        let new_generator =
+            || SyscallGenerator::new(max_calls, Context::new(syscall_metadata.clone()));
+        #[cfg(feature = "bytes")]
+        let new_generator = || RandBytesGenerator::new(max_size.try_into().unwrap());
+
+        // Build the fuzzer with the scheduler and the mutational stage, and run the fuzz loop.
+        // A macro is used since they have different types for different schedulers.
+        macro_rules! fuzz_with {
+            ($scheduler:expr, $mutational_stage:expr) => {{
+                // A fuzzer with feedbacks and a corpus scheduler
+                let mut fuzzer = StdFuzzer::new($scheduler, feedback, objective);
 
-        // Create a QEMU in-process executor
-        let mut executor = QemuExecutor::new(
-            emulator,
-            &mut harness,
-            tuple_list!(edges_observer, time_observer),
-            &mut fuzzer,
-            &mut state,
-            &mut mgr,
-            timeout,
-        )
-        .expect("Failed to create QemuExecutor");
-
-        // Instead of calling the timeout handler and restart the process, trigger a breakpoint ASAP
-        executor.break_on_timeout();
-
-        if state.must_load_initial_inputs() {
-            let dirs = [init_corpus.clone(), gen_corpus.clone()];
-            if state
-                .load_initial_inputs(&mut fuzzer, &mut executor, &mut mgr, &dirs)
-                .is_ok()
-                && state.corpus().count() > 0
-            {
-                println!("We imported {} inputs from disk.", state.corpus().count());
-            } else {
-                println!("Failed to import initial inputs, try to generate");
-                #[cfg(not(feature = "bytes"))]
-                let context = Context::new(syscall_metadata.clone());
                 #[cfg(not(feature = "bytes"))]
-                let mut generator = SyscallGenerator::new(max_calls, context);
-                #[cfg(feature = "bytes")]
-                let mut generator = RandBytesGenerator::new(max_size.try_into().unwrap());
-                state
-                    .generate_initial_inputs(
-                        &mut fuzzer,
-                        &mut executor,
-                        &mut generator,
-                        &mut mgr,
-                        4,
+                let mut stages = tuple_list!(
+                    $mutational_stage,
+                    GenerationStage::new(new_generator(), generate_prob),
+                    CalibrationStage::new(&calibration_feedback),
+                    CorpusRarityStage::new(),
+                    MutatorStatsStage::new(),
+                    SyscallStatsStage::new(
+                        syscall_metadata.clone(),
+                        syscall_stats
+                            .as_ref()
+                            .map(|dir| dir.join(format!("syscall-stats-{}.json", core_id.0)))
                     )
-                    .expect("Failed to generate initial corpus");
-                println!("We generated {} inputs.", state.corpus().count());
-            }
+                );
+                #[cfg(feature = "bytes")]
+                let mut stages = tuple_list!(
+                    $mutational_stage,
+                    GenerationStage::new(new_generator(), generate_prob),
+                    CalibrationStage::new(&calibration_feedback)
+                );
+
+                // Create a QEMU in-process executor
+                let mut executor = QemuExecutor::new(
+                    emulator,
+                    &mut harness,
+                    tuple_list!(edges_observer, time_observer),
+                    &mut fuzzer,
+                    &mut state,
+                    &mut mgr,
+                    timeout,
+                )
+                .expect("Failed to create QemuExecutor");
+
+                // Instead of calling the timeout handler and restart the process, trigger a breakpoint ASAP
+                executor.break_on_timeout();
+
+                // Calibrate timeouts, and re-execute crashes and timeouts to verify them
+                let mut executor = VerifyExecutor::new(
+                    executor,
+                    verify_runs,
+                    timeouts,
+                    |executor, timeout| executor.inner_mut().set_timeout(timeout),
+                );
+
+                if state.must_load_initial_inputs() {
+                    let dirs = [init_corpus.clone(), gen_corpus.clone()];
+                    match state.load_initial_inputs(&mut fuzzer, &mut executor, &mut mgr, &dirs) {
+                        Ok(()) => {
+                            println!("We imported {} inputs from disk.", state.corpus().count())
+                        }
+                        Err(err) => println!("Failed to import initial inputs: {err:?}"),
+                    }
+
+                    // Generate more inputs if the corpus is too small
+                    let count = state.corpus().count();
+                    if count < init_count {
+                        let mut generator = new_generator();
+                        state
+                            .generate_initial_inputs(
+                                &mut fuzzer,
+                                &mut executor,
+                                &mut generator,
+                                &mut mgr,
+                                init_count - count,
+                            )
+                            .expect("Failed to generate initial corpus");
+                        println!(
+                            "We generated {} inputs.",
+                            state.corpus().count() - count
+                        );
+                    }
+                }
+
+                fuzzer
+                    .fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr)
+                    .unwrap();
+            }};
         }
 
-        fuzzer
-            .fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr)
-            .unwrap();
+        // Minimization of the corpus is applied to all schedulers
+        match scheduler {
+            SchedulerKind::Queue => fuzz_with!(
+                IndexesLenTimeMinimizerScheduler::new(&edges_observer, QueueScheduler::new()),
+                StdMutationalStage::new(mutator)
+            ),
+            SchedulerKind::Power => fuzz_with!(
+                IndexesLenTimeMinimizerScheduler::new(
+                    &edges_observer,
+                    PowerQueueScheduler::new(&mut state, &edges_observer, schedule)
+                ),
+                StdPowerMutationalStage::new(mutator)
+            ),
+            SchedulerKind::Weighted => fuzz_with!(
+                IndexesLenTimeMinimizerScheduler::new(
+                    &edges_observer,
+                    StdWeightedScheduler::with_schedule(
+                        &mut state,
+                        &edges_observer,
+                        Some(schedule)
+                    )
+                ),
+                StdPowerMutationalStage::new(mutator)
+            ),
+            #[cfg(not(feature = "bytes"))]
+            SchedulerKind::Rare => fuzz_with!(
+                IndexesLenTimeMinimizerScheduler::new(
+                    &edges_observer,
+                    WeightedScheduler::<_, RareTestcaseScore<_>, _, _>::with_schedule(
+                        &mut state,
+                        &edges_observer,
+                        Some(schedule)
+                    )
+                ),
+                StdPowerMutationalStage::new(mutator)
+            ),
+        }
         Ok(())
     };
 
     // The shared memory allocator
     let shmem_provider = StdShMemProvider::new().expect("Failed to init shared memory");
 
-    // The stats reporter for the broker
-    let monitor = MultiMonitor::new(|s| println!("{s}"));
+    // Build and run a Launcher, with the stats reporter for the broker, also writing
+    // the progress into the stats file
+    macro_rules! launch_with {
+        ($monitor:expr) => {{
+            let monitor = StatsFileMonitor::new(
+                $monitor,
+                stats_file.as_deref(),
+                Duration::from_secs(stats_interval),
+            );
+            Launcher::builder()
+                .shmem_provider(shmem_provider)
+                .broker_port(broker_port)
+                .configuration(EventConfig::from_build_id())
+                .monitor(monitor)
+                .run_client(&mut run_client)
+                .cores(&cores)
+                // Outputs of clients would break the terminal UI
+                .stdout_file(tui.then_some("/dev/null"))
+                .build()
+                .launch()
+        }};
+    }
 
     // let monitor = SimpleMonitor::new(|s| println!("{s}"));
     // let mut mgr = SimpleEventManager::new(monitor);
     // run_client(None, mgr, 0);
 
-    // Build and run a Launcher
-    match Launcher::builder()
-        .shmem_provider(shmem_provider)
-        .broker_port(broker_port)
-        .configuration(EventConfig::from_build_id())
-        .monitor(monitor)
-        .run_client(&mut run_client)
-        .cores(&cores)
-        // .stdout_file(Some("/dev/null"))
-        .build()
-        .launch()
-    {
+    let result = if tui {
+        launch_with!(TuiMonitor::builder()
+            .title("Flicker")
+            .version(env!("CARGO_PKG_VERSION"))
+            .enhanced_graphics(true)
+            .build())
+    } else {
+        launch_with!(MultiMonitor::new(|s| println!("{s}")))
+    };
+    match result {
         Ok(()) => (),
         Err(Error::ShuttingDown) => println!("Fuzzing stopped by user. Good bye."),
         Err(err) => panic!("Failed to run launcher: {err:?}"),
     }
 }
+
+fn to_power_schedule(kind: PowerScheduleKind) -> PowerSchedule {
+    match kind {
+        PowerScheduleKind::Explore => PowerSchedule::EXPLORE,
+        PowerScheduleKind::Exploit => PowerSchedule::EXPLOIT,
+        PowerScheduleKind::Fast => PowerSchedule::FAST,
+        PowerScheduleKind::Coe => PowerSchedule::COE,
+        PowerScheduleKind::Lin => PowerSchedule::LIN,
+        PowerScheduleKind::Quad => PowerSchedule::QUAD,
+    }
+}
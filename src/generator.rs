@@ -9,7 +9,7 @@ use crate::input::SyscallInput;
 use crate::program::{
     call::{Arg, Call},
     context::Context,
-    syscall::{Field, GenerateArg, Syscall, Type},
+    syscall::{update_computed_fields, Field, GenerateArg, Syscall, Type},
 };
 use crate::utility::binary;
 
@@ -74,7 +74,8 @@ where
 }
 
 pub fn generate_call<R: Rand>(rand: &mut R, ctx: &mut Context, syscall: &Syscall) -> Vec<Call> {
-    let (args, mut calls) = generate_args(rand, ctx, syscall.fields());
+    let (mut args, mut calls) = generate_args(rand, ctx, syscall.fields());
+    update_computed_fields(syscall.fields(), &mut args, None, None);
     let id = syscall
         .return_type()
         .filter(|ty| ty.is_resource())
@@ -91,7 +92,10 @@ pub fn generate_args<R: Rand>(
 ) -> (Vec<Arg>, Vec<Call>) {
     let (args, calls): (Vec<Arg>, Vec<Vec<Call>>) = fields
         .iter()
-        .map(|field| generate_arg(rand, ctx, &field.ty))
+        .map(|field| {
+            let (arg, calls) = generate_arg(rand, ctx, &field.ty);
+            (field.present(arg), calls)
+        })
         .unzip();
     let calls = calls.into_iter().flatten().collect();
     (args, calls)
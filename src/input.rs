@@ -59,6 +59,17 @@ impl SyscallInput {
         self.calls.splice(idx..idx, calls);
     }
 
+    /// Swap the calls at the given indices.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.calls.swap(a, b);
+    }
+
+    /// Move the call at `from` to `to`, shifting the calls in between.
+    pub fn relocate(&mut self, from: usize, to: usize) {
+        let call = self.calls.remove(from);
+        self.calls.insert(to, call);
+    }
+
     /// Remove the call at the given index.
     pub fn remove(&mut self, idx: usize, metadata: &SyscallMetadata) {
         let call = self.calls.remove(idx);
@@ -1,6 +1,13 @@
+pub mod executor;
+pub mod feedback;
 pub mod generator;
 pub mod input;
+pub mod monitor;
 pub mod mutator;
 pub mod parser;
 pub mod program;
+pub mod scheduler;
+pub mod stages;
+pub mod stats;
+pub mod trace;
 mod utility;
@@ -1,3 +1,9 @@
+#[cfg(target_os = "linux")]
+mod binutils;
+
+#[cfg(target_os = "linux")]
+mod filter;
+
 #[cfg(target_os = "linux")]
 mod fuzzer;
 
@@ -7,6 +13,15 @@ mod runner;
 #[cfg(target_os = "linux")]
 mod cli;
 
+#[cfg(target_os = "linux")]
+mod coverage;
+
+#[cfg(all(target_os = "linux", not(feature = "bytes")))]
+mod export;
+
+#[cfg(target_os = "linux")]
+mod snapshot;
+
 #[cfg(target_os = "linux")]
 pub fn main() {
     use cli::Commands;
@@ -19,6 +34,9 @@ pub fn main() {
     match cli.command {
         Commands::Fuzz(options) => fuzzer::fuzz(options),
         Commands::Reproduce(options) => runner::reproduce(options),
+        Commands::Coverage(options) => coverage::coverage(options),
+        #[cfg(not(feature = "bytes"))]
+        Commands::Export(options) => export::export(options),
     }
 }
 
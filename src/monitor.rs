@@ -0,0 +1,200 @@
+//! Monitors of the fuzzer.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use libafl::monitors::{ClientStats, Monitor, UserStatsValue};
+use libafl_bolts::{current_time, ClientId};
+use log::warn;
+use serde_json::json;
+
+/// Format of the stats file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StatsFormat {
+    /// One JSON object per line
+    JsonLines,
+    /// One row for all clients and one row for each client
+    Csv,
+}
+
+/// Progress of the campaign, overall or of a client.
+#[derive(Debug, Clone, Copy)]
+struct Progress {
+    execs: u64,
+    execs_per_sec: f64,
+    corpus: u64,
+    /// Edges of the client, or the max of all clients overall, as the union of their
+    /// coverage is unknown to the broker
+    max_client_edges: u64,
+    crashes: u64,
+}
+
+impl Progress {
+    fn of_client(client: &mut ClientStats, now: Duration) -> Self {
+        Self {
+            execs: client.executions,
+            execs_per_sec: client.execs_per_sec(now),
+            corpus: client.corpus_size,
+            max_client_edges: edges(client),
+            crashes: client.objective_size,
+        }
+    }
+
+    fn to_csv(self, time: u64, client: &str) -> String {
+        format!(
+            "{time},{client},{},{:.2},{},{},{}\n",
+            self.execs, self.execs_per_sec, self.corpus, self.max_client_edges, self.crashes
+        )
+    }
+}
+
+/// Wrap a monitor, additionally appending the progress to a file periodically in JSON lines,
+/// or in CSV if the file name ends with `.csv`, for plotting.
+#[derive(Debug, Clone)]
+pub struct StatsFileMonitor<M> {
+    inner: M,
+    file: Option<(PathBuf, StatsFormat)>,
+    interval: Duration,
+    last_write: Duration,
+}
+
+impl<M> StatsFileMonitor<M> {
+    pub fn new(inner: M, path: Option<&Path>, interval: Duration) -> Self {
+        let file = path.map(|path| {
+            let format = if path.extension().is_some_and(|ext| ext == "csv") {
+                StatsFormat::Csv
+            } else {
+                StatsFormat::JsonLines
+            };
+            if format == StatsFormat::Csv && !path.exists() {
+                fs::write(
+                    path,
+                    "time,client,execs,execs_per_sec,corpus,max_client_edges,crashes\n",
+                )
+                .unwrap_or_else(|e| panic!("Failed to create stats file {path:?}: {e}"));
+            }
+            (path.to_path_buf(), format)
+        });
+        Self {
+            inner,
+            file,
+            interval,
+            last_write: Duration::ZERO,
+        }
+    }
+}
+
+impl<M: Monitor> StatsFileMonitor<M> {
+    fn write_stats(&mut self) {
+        let now = current_time();
+        if now.saturating_sub(self.last_write) < self.interval {
+            return;
+        }
+        self.last_write = now;
+
+        let time = now.saturating_sub(self.inner.start_time()).as_secs();
+        let clients: Vec<(usize, Progress)> = self
+            .inner
+            .client_stats_mut()
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, client)| client.enabled)
+            .map(|(id, client)| (id, Progress::of_client(client, now)))
+            .collect();
+        let total = Progress {
+            execs: self.inner.total_execs(),
+            execs_per_sec: self.inner.execs_per_sec(),
+            corpus: self.inner.corpus_size(),
+            max_client_edges: clients
+                .iter()
+                .map(|(_, c)| c.max_client_edges)
+                .max()
+                .unwrap_or(0),
+            crashes: self.inner.objective_size(),
+        };
+
+        let Some((path, format)) = &self.file else {
+            return;
+        };
+        let text = match format {
+            StatsFormat::JsonLines => {
+                let clients: Vec<_> = clients
+                    .iter()
+                    .map(|(id, c)| {
+                        json!({
+                            "client": id,
+                            "execs": c.execs,
+                            "execs_per_sec": c.execs_per_sec,
+                            "corpus": c.corpus,
+                            "edges": c.max_client_edges,
+                            "crashes": c.crashes,
+                        })
+                    })
+                    .collect();
+                let line = json!({
+                    "time": time,
+                    "execs": total.execs,
+                    "execs_per_sec": total.execs_per_sec,
+                    "corpus": total.corpus,
+                    "max_client_edges": total.max_client_edges,
+                    "crashes": total.crashes,
+                    "clients": clients,
+                });
+                format!("{line}\n")
+            }
+            StatsFormat::Csv => {
+                let mut rows = total.to_csv(time, "all");
+                for (id, client) in &clients {
+                    rows.push_str(&client.to_csv(time, &id.to_string()));
+                }
+                rows
+            }
+        };
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(text.as_bytes()));
+        if let Err(e) = result {
+            warn!("[StatsFileMonitor::write_stats] Failed to write stats to {path:?}: {e}");
+        }
+    }
+}
+
+impl<M: Monitor> Monitor for StatsFileMonitor<M> {
+    fn client_stats_mut(&mut self) -> &mut Vec<ClientStats> {
+        self.inner.client_stats_mut()
+    }
+
+    fn client_stats(&self) -> &[ClientStats] {
+        self.inner.client_stats()
+    }
+
+    fn start_time(&self) -> Duration {
+        self.inner.start_time()
+    }
+
+    fn set_start_time(&mut self, time: Duration) {
+        self.inner.set_start_time(time)
+    }
+
+    fn aggregate(&mut self, name: &str) {
+        self.inner.aggregate(name)
+    }
+
+    fn display(&mut self, event_msg: &str, sender_id: ClientId) {
+        self.inner.display(event_msg, sender_id);
+        self.write_stats();
+    }
+}
+
+/// Number of edges found by the client, reported by the map feedback as user stats.
+fn edges(client: &ClientStats) -> u64 {
+    match client.get_user_stats("edges").map(|stats| stats.value()) {
+        Some(UserStatsValue::Ratio(found, _)) => *found,
+        Some(UserStatsValue::Number(found)) => *found,
+        _ => 0,
+    }
+}
@@ -1,24 +1,32 @@
 use std::borrow::Cow;
+use std::fmt;
 use std::iter;
+use std::str::FromStr;
 
 use libafl::{
-    corpus::Corpus,
+    corpus::{Corpus, CorpusId},
     inputs::UsesInput,
-    mutators::{MutationResult, Mutator},
+    mutators::{MutationResult, Mutator, MutatorsTuple},
     random_corpus_id,
     state::{HasCorpus, HasMaxSize, HasRand},
-    Error,
+    Error, HasMetadata,
 };
 use libafl_bolts::{
+    nonzero,
     rands::Rand,
     tuples::{tuple_list, tuple_list_type},
-    HasLen, Named,
+    HasLen, Named, SerdeAny,
 };
 
 use log::debug;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::program::context::Context;
-use crate::{generator::generate_call, program::syscall::MutateArg};
+use crate::program::{call::Call, context::Context};
+use crate::{
+    generator::generate_call,
+    program::syscall::{update_computed_fields, MutateArg},
+};
 use crate::{input::SyscallInput, program::metadata::SyscallMetadata};
 
 pub struct SyscallSpliceMutator;
@@ -118,7 +126,10 @@ pub struct SyscallRandMutator {
 
 impl<S> Mutator<SyscallInput, S> for SyscallRandMutator
 where
-    S: HasRand + HasMaxSize,
+    S: UsesInput<Input = SyscallInput>
+        + HasRand
+        + HasCorpus<Corpus: Corpus<Input = SyscallInput>>
+        + HasMaxSize,
 {
     /// Mutate a random argument of a random syscall
     fn mutate(&mut self, state: &mut S, input: &mut SyscallInput) -> Result<MutationResult, Error> {
@@ -143,9 +154,29 @@ where
             .rand_mut()
             .below(syscall.fields().len().try_into().unwrap());
         let field = &syscall.fields()[arg_pos];
+
+        // The same argument in a random corpus entry, whose buffers of the same field
+        // are used for crossover
+        if state.corpus().count() > 0 {
+            let id = random_corpus_id!(state.corpus(), state.rand_mut());
+            let other = state.corpus().get(id)?.borrow();
+            if let Some(other) = other.input() {
+                let donors = other
+                    .calls()
+                    .iter()
+                    .filter(|c| c.number() == call.number())
+                    .map(|c| c.args()[arg_pos].clone())
+                    .collect();
+                ctx.set_donors(donors);
+            }
+        }
+
         let arg = &mut call.args_mut()[arg_pos];
         let calls = field.mutate(state.rand_mut(), &mut ctx, arg);
 
+        // Keep lengths and checksums consistent, except for the mutated one
+        update_computed_fields(syscall.fields(), call.args_mut(), None, Some(arg_pos));
+
         // Insert new calls if any
         input.insert(call_pos, calls.into_iter());
 
@@ -193,14 +224,202 @@ impl Named for SyscallRemoveMutator {
     }
 }
 
-pub fn syscall_mutations(
-    metadata: SyscallMetadata,
-) -> tuple_list_type!(
+pub struct SyscallSwapMutator;
+
+impl<S> Mutator<SyscallInput, S> for SyscallSwapMutator
+where
+    S: HasRand,
+{
+    /// Swap two random calls, if the resources are still produced before used
+    fn mutate(&mut self, state: &mut S, input: &mut SyscallInput) -> Result<MutationResult, Error> {
+        if input.len() < 2 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let a = state.rand_mut().below(input.len().try_into().unwrap());
+        let b = state.rand_mut().below(input.len().try_into().unwrap());
+        let (first, second) = (a.min(b), a.max(b));
+        if first == second {
+            return Ok(MutationResult::Skipped);
+        }
+
+        // The second call moves before the calls in between, and the first call moves after them
+        let calls = input.calls();
+        if uses_any_result(&calls[second], &calls[first..second])
+            || is_result_used(&calls[first], &calls[first + 1..=second])
+        {
+            return Ok(MutationResult::Skipped);
+        }
+        input.swap(first, second);
+
+        debug!("[SyscallSwapMutator::mutate] Swapped calls at position {first} and {second}");
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for SyscallSwapMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("SyscallSwapMutator");
+        &NAME
+    }
+}
+
+pub struct SyscallDuplicateMutator;
+
+impl<S> Mutator<SyscallInput, S> for SyscallDuplicateMutator
+where
+    S: HasRand + HasMaxSize,
+{
+    /// Duplicate a random call to a later position, using the same resources
+    fn mutate(&mut self, state: &mut S, input: &mut SyscallInput) -> Result<MutationResult, Error> {
+        if input.len() == 0 || input.len() >= state.max_size() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let pos = state.rand_mut().below(input.len().try_into().unwrap());
+        // Resources used by the call are all produced before it, so any later position works
+        let new_pos = state.rand_mut().between(pos + 1, input.len());
+
+        // The duplicated call produces a new result, if any
+        let call = input.get(pos);
+        let new_call = Call::new(
+            call.number(),
+            call.args().to_vec(),
+            call.result().map(|_| Uuid::new_v4()),
+        );
+        input.insert(new_pos, iter::once(new_call));
+
+        debug!("[SyscallDuplicateMutator::mutate] Duplicated call at position {pos} to {new_pos}");
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for SyscallDuplicateMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("SyscallDuplicateMutator");
+        &NAME
+    }
+}
+
+pub struct SyscallMoveMutator;
+
+impl<S> Mutator<SyscallInput, S> for SyscallMoveMutator
+where
+    S: HasRand,
+{
+    /// Move a random call earlier or later, if the resources are still produced before used
+    fn mutate(&mut self, state: &mut S, input: &mut SyscallInput) -> Result<MutationResult, Error> {
+        if input.len() < 2 {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let from = state.rand_mut().below(input.len().try_into().unwrap());
+        let to = state.rand_mut().below(input.len().try_into().unwrap());
+        let calls = input.calls();
+        let valid = if to < from {
+            // The moved call can't use results of the calls it skips over
+            !uses_any_result(&calls[from], &calls[to..from])
+        } else if to > from {
+            // Skipped calls can't use the result of the moved call
+            !is_result_used(&calls[from], &calls[from + 1..=to])
+        } else {
+            false
+        };
+        if !valid {
+            return Ok(MutationResult::Skipped);
+        }
+        input.relocate(from, to);
+
+        debug!("[SyscallMoveMutator::mutate] Moved call at position {from} to {to}");
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for SyscallMoveMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("SyscallMoveMutator");
+        &NAME
+    }
+}
+
+/// Whether the call uses any result produced by the calls.
+fn uses_any_result(call: &Call, calls: &[Call]) -> bool {
+    calls
+        .iter()
+        .filter_map(|c| c.result())
+        .any(|id| call.uses_result(id))
+}
+
+/// Whether the result of the call is used by any of the calls.
+fn is_result_used(call: &Call, calls: &[Call]) -> bool {
+    call.result()
+        .is_some_and(|id| calls.iter().any(|c| c.uses_result(id)))
+}
+
+/// Kinds of syscall mutators, in the same order as in [`syscall_mutations`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallMutatorKind {
+    Splice,
+    Insert,
+    Rand,
+    Remove,
+    Swap,
+    Duplicate,
+    Move,
+}
+
+impl SyscallMutatorKind {
+    pub const ALL: [SyscallMutatorKind; 7] = [
+        SyscallMutatorKind::Splice,
+        SyscallMutatorKind::Insert,
+        SyscallMutatorKind::Rand,
+        SyscallMutatorKind::Remove,
+        SyscallMutatorKind::Swap,
+        SyscallMutatorKind::Duplicate,
+        SyscallMutatorKind::Move,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SyscallMutatorKind::Splice => "splice",
+            SyscallMutatorKind::Insert => "insert",
+            SyscallMutatorKind::Rand => "rand",
+            SyscallMutatorKind::Remove => "remove",
+            SyscallMutatorKind::Swap => "swap",
+            SyscallMutatorKind::Duplicate => "duplicate",
+            SyscallMutatorKind::Move => "move",
+        }
+    }
+}
+
+impl FromStr for SyscallMutatorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| format!("Unknown mutator: {s}"))
+    }
+}
+
+impl fmt::Display for SyscallMutatorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+pub type SyscallMutationsType = tuple_list_type!(
     SyscallSpliceMutator,
     SyscallInsertMutator,
     SyscallRandMutator,
-    SyscallRemoveMutator
-) {
+    SyscallRemoveMutator,
+    SyscallSwapMutator,
+    SyscallDuplicateMutator,
+    SyscallMoveMutator
+);
+
+pub fn syscall_mutations(metadata: SyscallMetadata) -> SyscallMutationsType {
     tuple_list!(
         SyscallSpliceMutator,
         SyscallInsertMutator {
@@ -210,5 +429,146 @@ pub fn syscall_mutations(
             metadata: metadata.clone()
         },
         SyscallRemoveMutator { metadata },
+        SyscallSwapMutator,
+        SyscallDuplicateMutator,
+        SyscallMoveMutator,
     )
 }
+
+/// Max power of two of the number of stacked mutations, same as [`StdScheduledMutator`].
+///
+/// [`StdScheduledMutator`]: libafl::mutators::StdScheduledMutator
+const MAX_STACK_POW: usize = 7;
+
+/// Share of probability distributed uniformly in adaptive scheduling,
+/// so that unproductive mutators still get chances.
+const EXPLORATION: f64 = 0.1;
+
+/// Statistics of syscall mutators, stored in the state.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, SerdeAny)]
+pub struct SyscallMutatorStats {
+    /// Number of times each mutator mutated an input
+    applied: [u64; SyscallMutatorKind::ALL.len()],
+    /// Number of times each mutator mutated an input leading to a new corpus entry
+    successes: [u64; SyscallMutatorKind::ALL.len()],
+}
+
+impl SyscallMutatorStats {
+    pub fn applied(&self, kind: SyscallMutatorKind) -> u64 {
+        self.applied[kind as usize]
+    }
+
+    pub fn successes(&self, kind: SyscallMutatorKind) -> u64 {
+        self.successes[kind as usize]
+    }
+
+    /// Smoothed success rate, so that mutators never applied are not ignored.
+    fn success_rate(&self, kind: SyscallMutatorKind) -> f64 {
+        (self.successes(kind) + 1) as f64 / (self.applied(kind) + 2) as f64
+    }
+}
+
+/// Scheduled mutator over [`syscall_mutations`], choosing only from the enabled ones.
+///
+/// If adaptive, like MOpt, mutators are chosen with probabilities proportional to
+/// how often they find new coverage, which is recorded in [`SyscallMutatorStats`].
+/// Otherwise they are chosen uniformly.
+pub struct SyscallScheduledMutator {
+    mutations: SyscallMutationsType,
+    enabled: Vec<SyscallMutatorKind>,
+    adaptive: bool,
+    /// Mutators successfully applied in the last run, may contain duplicates
+    last_applied: Vec<SyscallMutatorKind>,
+}
+
+impl SyscallScheduledMutator {
+    pub fn new(metadata: SyscallMetadata, enabled: &[SyscallMutatorKind], adaptive: bool) -> Self {
+        let mut enabled = enabled.to_vec();
+        enabled.sort_by_key(|kind| *kind as usize);
+        enabled.dedup();
+        assert!(
+            !enabled.is_empty(),
+            "At least one mutator has to be enabled"
+        );
+        Self {
+            mutations: syscall_mutations(metadata),
+            enabled,
+            adaptive,
+            last_applied: Vec::new(),
+        }
+    }
+
+    /// Choose a mutator to apply.
+    fn schedule<S: HasRand + HasMetadata>(&self, state: &mut S) -> SyscallMutatorKind {
+        let stats = state.metadata::<SyscallMutatorStats>().ok();
+        let weights: Vec<f64> = match stats {
+            Some(stats) if self.adaptive => {
+                let rates: Vec<f64> = self
+                    .enabled
+                    .iter()
+                    .map(|kind| stats.success_rate(*kind))
+                    .collect();
+                let total: f64 = rates.iter().sum();
+                let uniform = 1.0 / self.enabled.len() as f64;
+                rates
+                    .iter()
+                    .map(|rate| (1.0 - EXPLORATION) * rate / total + EXPLORATION * uniform)
+                    .collect()
+            }
+            _ => vec![1.0; self.enabled.len()],
+        };
+
+        let mut target = state.rand_mut().next_float() * weights.iter().sum::<f64>();
+        for (kind, weight) in self.enabled.iter().zip(&weights) {
+            if target < *weight {
+                return *kind;
+            }
+            target -= weight;
+        }
+        // Rounding errors
+        *self.enabled.last().unwrap()
+    }
+}
+
+impl<S> Mutator<SyscallInput, S> for SyscallScheduledMutator
+where
+    S: HasRand + HasMetadata,
+    SyscallMutationsType: MutatorsTuple<SyscallInput, S>,
+{
+    /// Stack a random number of mutations chosen from the enabled ones
+    fn mutate(&mut self, state: &mut S, input: &mut SyscallInput) -> Result<MutationResult, Error> {
+        self.last_applied.clear();
+        let iters = 1 << (1 + state.rand_mut().below(nonzero!(MAX_STACK_POW)));
+        let mut result = MutationResult::Skipped;
+        for _ in 0..iters {
+            let kind = self.schedule(state);
+            let outcome = self
+                .mutations
+                .get_and_mutate((kind as usize).into(), state, input)?;
+            if outcome == MutationResult::Mutated {
+                self.last_applied.push(kind);
+                result = MutationResult::Mutated;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Credit the applied mutators if the input is added to the corpus
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        let stats = state.metadata_or_insert_with(SyscallMutatorStats::default);
+        for kind in self.last_applied.drain(..) {
+            stats.applied[kind as usize] += 1;
+            if new_corpus_id.is_some() {
+                stats.successes[kind as usize] += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Named for SyscallScheduledMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("SyscallScheduledMutator");
+        &NAME
+    }
+}
@@ -1,18 +1,106 @@
-use std::path::Path;
+mod template;
 
-use syzlang_parser::parser::{Consts, Parsed, Statement};
+use std::fs;
+use std::path::{Path, PathBuf};
 
-pub fn parse(desc_path: &Path, const_path: &Path) -> Parsed {
-    println!("Parsing files: {:?}, {:?}", desc_path, const_path);
+use syzlang_parser::{
+    parser::{Consts, Parsed, Statement},
+    token::Token,
+};
 
-    let builtin = Statement::from_file(Path::new("desc/builtin.txt")).unwrap();
-    let desc = Statement::from_file(desc_path).unwrap();
-    let stmts = [builtin, desc].concat();
+use template::Templates;
+
+/// Builtin syzlang definitions, embedded so that the fuzzer does not
+/// depend on the current working directory.
+pub const BUILTIN: &str = include_str!("../desc/builtin.txt");
+
+/// Extension of syzlang description files.
+const DESC_EXTENSION: &str = "txt";
+
+/// Extension of syzlang constants files.
+const CONST_EXTENSION: &str = "const";
+
+/// Parse syzlang descriptions and constants.
+///
+/// Each path in `desc_paths` and `const_paths` can be either a file or a directory.
+/// For directories, all files with the matching extension (`.txt` for descriptions,
+/// `.const` for constants) directly under it are used, in lexicographical order.
+/// The builtin definitions are taken from `builtin_path` if given, otherwise the
+/// embedded [`BUILTIN`] is used. Type aliases and templates are expanded before
+/// parsing, see [`template`].
+pub fn parse(
+    builtin_path: Option<&Path>,
+    desc_paths: &[PathBuf],
+    const_paths: &[PathBuf],
+) -> Parsed {
+    let desc_files = expand_paths(desc_paths, DESC_EXTENSION);
+    let const_files = expand_paths(const_paths, CONST_EXTENSION);
+    println!("Parsing files: {:?}, {:?}", desc_files, const_files);
+
+    // Type aliases and templates may be used across files, so collect them from all
+    // descriptions before expanding each file
+    let mut templates = Templates::new();
+    let builtin = match builtin_path {
+        Some(path) => read_file(path),
+        None => BUILTIN.to_string(),
+    };
+    let builtin = templates.collect(&builtin);
+    let descs: Vec<String> = desc_files
+        .iter()
+        .map(|file| templates.collect(&read_file(file)))
+        .collect();
+
+    let builtin_name = builtin_path.map_or_else(|| "builtin".to_string(), |p| format!("{p:?}"));
+    let mut stmts = parse_desc(&templates.expand(&builtin), &builtin_name);
+    for (file, desc) in desc_files.iter().zip(&descs) {
+        stmts.extend(parse_desc(&templates.expand(desc), &format!("{file:?}")));
+    }
+    // Instantiated struct and union templates
+    stmts.extend(parse_desc(
+        &templates.take_instances(),
+        "instances of templates",
+    ));
 
     let mut consts = Consts::new(Vec::new());
-    consts.create_from_file(const_path).unwrap();
+    for file in &const_files {
+        consts
+            .create_from_file(file)
+            .unwrap_or_else(|e| panic!("Failed to parse constants {file:?}: {e:?}"));
+    }
 
     let mut parsed = Parsed::new(consts, stmts).unwrap();
     parsed.postprocess().unwrap();
     parsed
 }
+
+/// Parse an expanded description, with its name in error messages.
+fn parse_desc(text: &str, name: &str) -> Vec<Statement> {
+    let tokens = Token::create_from_str(text)
+        .unwrap_or_else(|e| panic!("Failed to parse description {name}: {e:?}"));
+    Statement::from_tokens(tokens)
+        .unwrap_or_else(|e| panic!("Failed to parse description {name}: {e:?}"))
+}
+
+fn read_file(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_else(|e| panic!("Failed to read {path:?}: {e}"))
+}
+
+/// Expand directories into the files with the given extension inside them.
+/// Plain files are kept as they are, regardless of their extensions.
+fn expand_paths(paths: &[PathBuf], extension: &str) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = fs::read_dir(path)
+                .unwrap_or_else(|e| panic!("Failed to read directory {path:?}: {e}"))
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == extension))
+                .collect();
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    files
+}
@@ -0,0 +1,422 @@
+//! Expansion of syzlang type aliases and templates.
+//!
+//! Type definitions (`type name body` and `type name[PARAMS] body`) are removed
+//! from the descriptions, and every use of them in a type position is replaced:
+//!
+//! - Aliases and type templates are substituted by their bodies with the
+//!   parameters replaced by the arguments, e.g. `buffer[in]` becomes
+//!   `ptr[in, array[int8]]`.
+//! - Struct and union templates are instantiated as new structs and unions
+//!   named after the template and its arguments, e.g. `optional[int32]`
+//!   becomes `optional_int32`, with the definition appended to the descriptions.
+//!
+//! Expansion repeats until nothing changes, so definitions may refer to each other.
+//! Definitions are collected from all descriptions first, so that each file can be
+//! expanded, and parsed, on its own.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Max rounds of expansion, to detect recursive definitions.
+const MAX_ROUNDS: usize = 32;
+
+/// Builtin types whose arguments are names of flags, fields or values rather than types,
+/// e.g. `filename` in `string[filename]` is a string flag set, with the least number of
+/// such arguments. Only the last argument after them, the underlying type like `int32`
+/// in `const[0, int32]`, is expanded.
+const NON_TYPE_ARGS: [(&str, usize); 16] = [
+    ("string", usize::MAX),
+    ("stringnoz", usize::MAX),
+    ("flags", 1),
+    ("len", 1),
+    ("bytesize", 1),
+    ("bytesize2", 1),
+    ("bytesize4", 1),
+    ("bytesize8", 1),
+    ("bitsize", 1),
+    ("offsetof", 1),
+    // `csum[parent, kind, proto, type]`, where `proto` is optional
+    ("csum", 2),
+    ("glob", usize::MAX),
+    ("const", 1),
+    ("proc", 2),
+    ("text", usize::MAX),
+    ("fmt", 1),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DefKind {
+    /// Alias or type template, body is a type
+    Type,
+    /// Struct or union template, body includes the brackets and attributes
+    Compound,
+}
+
+#[derive(Debug, Clone)]
+struct TypeDef {
+    params: Vec<String>,
+    body: String,
+    kind: DefKind,
+}
+
+/// Type definitions of all the descriptions, and the struct and union templates
+/// instantiated by expanding them.
+#[derive(Debug, Default)]
+pub struct Templates {
+    defs: BTreeMap<String, TypeDef>,
+    /// Names of all instances
+    instances: BTreeSet<String>,
+    /// Definitions of the instances not taken yet
+    new_defs: Vec<String>,
+}
+
+impl Templates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collect type definitions in the text, returning the text without them.
+    pub fn collect(&mut self, text: &str) -> String {
+        collect_defs(text, &mut self.defs)
+    }
+
+    /// Expand all type aliases and templates in the text.
+    pub fn expand(&mut self, text: &str) -> String {
+        let mut text = text.to_string();
+        for _ in 0..MAX_ROUNDS {
+            let (expanded, changed) = expand_once(
+                &text,
+                false,
+                &self.defs,
+                &mut self.instances,
+                &mut self.new_defs,
+            );
+            text = expanded;
+            if !changed {
+                return text;
+            }
+        }
+        panic!("Type aliases or templates are nested too deep, maybe recursive");
+    }
+
+    /// Definitions of the struct and union templates instantiated so far, expanded
+    /// themselves.
+    pub fn take_instances(&mut self) -> String {
+        let mut text = String::new();
+        for _ in 0..MAX_ROUNDS {
+            if self.new_defs.is_empty() {
+                return text;
+            }
+            // Expanding the definitions may instantiate more templates
+            for def in std::mem::take(&mut self.new_defs) {
+                let def = self.expand(&def);
+                text.push_str(&def);
+                text.push('\n');
+            }
+        }
+        panic!("Struct or union templates are nested too deep, maybe recursive");
+    }
+}
+
+/// Collect type definitions into `defs` and remove them from the text.
+fn collect_defs(text: &str, defs: &mut BTreeMap<String, TypeDef>) -> String {
+    let mut rest = String::new();
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        let Some(header) = line.strip_prefix("type ") else {
+            rest.push_str(line);
+            rest.push('\n');
+            continue;
+        };
+        let header = strip_comment(header).trim();
+
+        // Name and optional parameters, which are adjacent to the name
+        let name_end = header
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(header.len());
+        let name = header[..name_end].to_string();
+        let mut body = &header[name_end..];
+        let mut params = Vec::new();
+        if body.starts_with('[') {
+            let close = matching_bracket(body, 0).expect("Unclosed template parameters");
+            params = split_args(&body[1..close]);
+            body = &body[close + 1..];
+        }
+        let body = body.trim();
+
+        let def = if body == "{" || body == "[" {
+            // Struct or union template, ends with a line starting with the closing bracket
+            let close = if body == "{" { '}' } else { ']' };
+            let mut compound = format!("{body}\n");
+            for line in lines.by_ref() {
+                compound.push_str(strip_comment(line).trim_end());
+                compound.push('\n');
+                rest.push('\n');
+                if line.starts_with(close) {
+                    break;
+                }
+            }
+            TypeDef {
+                params,
+                body: compound,
+                kind: DefKind::Compound,
+            }
+        } else {
+            TypeDef {
+                params,
+                body: body.to_string(),
+                kind: DefKind::Type,
+            }
+        };
+        // Keep the line count for better error messages from the parser
+        rest.push('\n');
+        defs.insert(name, def);
+    }
+
+    rest
+}
+
+/// Expand uses of type definitions once, returning the new text and whether it is changed.
+/// `text` is a single type if `is_type`, or descriptions otherwise. Definitions of newly
+/// instantiated struct and union templates are put in `new_defs`.
+fn expand_once(
+    text: &str,
+    is_type: bool,
+    defs: &BTreeMap<String, TypeDef>,
+    instances: &mut BTreeSet<String>,
+    new_defs: &mut Vec<String>,
+) -> (String, bool) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut changed = false;
+
+    // Stack of open brackets
+    let mut brackets: Vec<char> = Vec::new();
+    // Last non-whitespace character, and whether an identifier has been seen in this line
+    let mut prev: Option<char> = None;
+    let mut line_has_ident = is_type;
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '#' => {
+                // Copy comments verbatim
+                while i < chars.len() && chars[i] != '\n' {
+                    out.push(chars[i]);
+                    i += 1;
+                }
+                continue;
+            }
+            '"' | '\'' | '`' => {
+                // Copy strings verbatim
+                out.push(c);
+                i += 1;
+                while i < chars.len() && chars[i] != c {
+                    out.push(chars[i]);
+                    i += 1;
+                }
+                if i < chars.len() {
+                    out.push(chars[i]);
+                    i += 1;
+                }
+                prev = Some(c);
+                continue;
+            }
+            '\n' => {
+                line_has_ident = false;
+                prev = None;
+                out.push(c);
+                i += 1;
+                continue;
+            }
+            '(' | '[' | '{' => brackets.push(c),
+            ')' | ']' | '}' => {
+                brackets.pop();
+            }
+            _ => {}
+        }
+
+        if c.is_ascii_digit() {
+            // Numbers, which may contain letters like `0xff`
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                out.push(chars[i]);
+                i += 1;
+            }
+            prev = Some(c);
+            continue;
+        }
+
+        if !(c.is_ascii_alphabetic() || c == '_') {
+            if !c.is_whitespace() {
+                prev = Some(c);
+            }
+            out.push(c);
+            i += 1;
+            continue;
+        }
+
+        // Identifier
+        let start = i;
+        // `$` is included for syscall variants like `openat$dir`
+        while i < chars.len() && (chars[i].is_ascii_alphanumeric() || "_$".contains(chars[i])) {
+            i += 1;
+        }
+        let word: String = chars[start..i].iter().collect();
+
+        // Names of syscalls, fields and arguments are not types
+        let is_name = !line_has_ident
+            || prev == Some('(')
+            || (prev == Some(',') && brackets.last() == Some(&'('));
+        line_has_ident = true;
+        prev = Some('a');
+
+        let non_type_args = NON_TYPE_ARGS
+            .iter()
+            .find(|(name, _)| *name == word)
+            .map(|(_, count)| *count);
+        if let Some(count) = non_type_args.filter(|_| chars.get(i) == Some(&'[')) {
+            let rest: String = chars[i..].iter().collect();
+            let close = matching_bracket(&rest, 0).expect("Unclosed type arguments");
+            out.push_str(&word);
+            i += rest[..=close].chars().count();
+            prev = Some(']');
+
+            let mut args = split_args(&rest[1..close]);
+            let has_type = args.len() > count;
+            match args.last_mut() {
+                // Expand the underlying type, copying the others verbatim
+                Some(last) if has_type => {
+                    let (expanded, last_changed) =
+                        expand_once(last, true, defs, instances, new_defs);
+                    *last = expanded;
+                    changed |= last_changed;
+                    out.push('[');
+                    out.push_str(&args.join(", "));
+                    out.push(']');
+                }
+                _ => out.push_str(&rest[..=close]),
+            }
+            continue;
+        }
+
+        let Some(def) = defs.get(&word).filter(|_| !is_name) else {
+            out.push_str(&word);
+            continue;
+        };
+
+        // Template arguments
+        let mut args = Vec::new();
+        if !def.params.is_empty() {
+            let rest: String = chars[i..].iter().collect();
+            if rest.starts_with('[') {
+                let close = matching_bracket(&rest, 0).expect("Unclosed template arguments");
+                args = split_args(&rest[1..close]);
+                i += rest[..=close].chars().count();
+            }
+        }
+        assert_eq!(
+            args.len(),
+            def.params.len(),
+            "Wrong number of arguments for template {word}"
+        );
+
+        let body = substitute(&def.body, &def.params, &args);
+        match def.kind {
+            DefKind::Type => out.push_str(&body),
+            DefKind::Compound => {
+                let name = mangle(&word, &args);
+                if instances.insert(name.clone()) {
+                    new_defs.push(format!("{name} {body}"));
+                }
+                out.push_str(&name);
+            }
+        }
+        changed = true;
+    }
+
+    (out, changed)
+}
+
+/// Replace whole-word parameters by the arguments.
+fn substitute(body: &str, params: &[String], args: &[String]) -> String {
+    let mut out = String::with_capacity(body.len());
+    let mut word = String::new();
+    for c in body.chars().chain(std::iter::once('\0')) {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            word.push(c);
+            continue;
+        }
+        match params.iter().position(|p| *p == word) {
+            Some(idx) => out.push_str(&args[idx]),
+            None => out.push_str(&word),
+        }
+        word.clear();
+        if c != '\0' {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Name of an instantiated struct or union template.
+fn mangle(name: &str, args: &[String]) -> String {
+    let mut mangled = name.to_string();
+    for arg in args {
+        mangled.push('_');
+        mangled.extend(arg.chars().map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        }));
+    }
+    mangled
+}
+
+/// Find the closing bracket matching the opening one at `open`.
+fn matching_bracket(text: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices().skip_while(|(i, _)| *i < open) {
+        match c {
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split template arguments at top-level commas.
+fn split_args(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut depth = 0;
+    let mut current = String::new();
+    for c in text.chars() {
+        match c {
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    if !current.trim().is_empty() {
+        args.push(current.trim().to_string());
+    }
+    args
+}
+
+fn strip_comment(line: &str) -> &str {
+    line.split_once('#').map_or(line, |(code, _)| code)
+}
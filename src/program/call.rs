@@ -6,10 +6,13 @@ use serde::{Deserialize, Serialize};
 use syscall2struct_helpers::{Pointer, SyscallResult};
 use uuid::Uuid;
 
+use super::metadata::truncate_to_ptr;
+
 /// Serialize to testcase bytes for execution on the target.
 /// Used as a helper for [`libafl::inputs::HasTargetBytes`].
 /// Currently should be implemented with [`postcard`] for harness
-/// to deserialize the bytes.
+/// to deserialize the bytes. Integers are encoded as varints independent of the
+/// byte order of the target, while addresses have to fit in its pointer width.
 #[enum_dispatch]
 pub trait ToExecBytes {
     /// Byte representation of this object.
@@ -43,6 +46,11 @@ impl Call {
     pub fn result(&self) -> Option<Uuid> {
         self.result
     }
+
+    /// Whether any argument of this call uses the given result.
+    pub fn uses_result(&self, id: Uuid) -> bool {
+        self.args.iter().any(|arg| arg.uses_result(id))
+    }
 }
 
 impl ToExecBytes for Call {
@@ -64,6 +72,8 @@ pub enum Arg {
     DataArg,
     GroupArg,
     ResultArg,
+    // Appended last to keep the serialized indices of the other variants
+    UnionArg,
 }
 
 // impl Arg {
@@ -82,6 +92,19 @@ pub enum Arg {
 //     }
 // }
 
+impl Arg {
+    /// Whether this argument or any of its sub-arguments uses the given result.
+    pub fn uses_result(&self, id: Uuid) -> bool {
+        match self {
+            Arg::ResultArg(inner) => inner.uses_result(id),
+            Arg::PointerArg(PointerArg::Data(arg)) => arg.uses_result(id),
+            Arg::GroupArg(group) => group.0.iter().any(|arg| arg.uses_result(id)),
+            Arg::UnionArg(union) => union.arg.uses_result(id),
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ConstArg(pub u64);
 
@@ -107,8 +130,7 @@ pub enum PointerArg {
 
 impl PointerArg {
     pub fn from_addr(addr: u64) -> Self {
-        assert_eq!(addr, 0, "Only null pointer is supported for now");
-        Self::Addr(addr)
+        Self::Addr(truncate_to_ptr(addr))
     }
 
     pub fn from_res(res: Arg) -> Self {
@@ -138,7 +160,8 @@ impl ToExecBytes for PointerArg {
         let idx = to_stdvec(&idx).unwrap();
 
         let data = match &self {
-            PointerArg::Addr(addr) => to_stdvec(addr).unwrap(),
+            // Addresses are `usize` in harness, so they must fit in the target's pointer width
+            PointerArg::Addr(addr) => to_stdvec(&truncate_to_ptr(*addr)).unwrap(),
             PointerArg::Data(data) => data.to_exec_bytes(),
         };
 
@@ -169,6 +192,14 @@ impl GroupArg {
     pub fn new(args: Vec<Arg>) -> Self {
         Self(args)
     }
+
+    pub fn args(&self) -> &[Arg] {
+        &self.0
+    }
+
+    pub fn args_mut(&mut self) -> &mut Vec<Arg> {
+        &mut self.0
+    }
 }
 
 impl ToExecBytes for GroupArg {
@@ -178,6 +209,43 @@ impl ToExecBytes for GroupArg {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnionArg {
+    index: usize,
+    arg: Box<Arg>,
+}
+
+impl UnionArg {
+    pub fn new(index: usize, arg: Arg) -> Self {
+        Self {
+            index,
+            arg: Box::new(arg),
+        }
+    }
+
+    /// Index of the chosen variant
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn arg(&self) -> &Arg {
+        &self.arg
+    }
+
+    pub fn arg_mut(&mut self) -> &mut Arg {
+        &mut self.arg
+    }
+}
+
+impl ToExecBytes for UnionArg {
+    fn to_exec_bytes(&self) -> Vec<u8> {
+        // Unions are enums in harness, serialized by postcard as
+        // u32 variant index followed by data
+        let idx = to_stdvec(&(self.index as u32)).unwrap();
+        [idx, self.arg.to_exec_bytes()].concat()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ResultArg {
     Ref(Uuid),
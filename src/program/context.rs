@@ -3,6 +3,8 @@ use uuid::Uuid;
 
 use super::{
     call::{Arg, Call, DataArg},
+    dictionary::Dictionary,
+    file_tree::FileTree,
     metadata::SyscallMetadata,
     syscall::{Syscall, Type},
 };
@@ -16,6 +18,9 @@ pub struct Context {
     strings: HashSet<String>,
     /// Set of filenames that have been generated
     filenames: HashSet<String>,
+    /// Arguments of the field being mutated in other testcases, whose buffers are
+    /// used for crossover in byte mutation
+    donors: Vec<Arg>,
     /// Whether a resource is being generated
     pub generating_resource: bool,
 }
@@ -28,6 +33,7 @@ impl Context {
             strings: HashSet::with_hasher(hash_builder.clone()),
             results: HashMap::with_hasher(hash_builder.clone()),
             filenames: HashSet::with_hasher(hash_builder),
+            donors: Vec::new(),
             generating_resource: false,
         }
     }
@@ -75,6 +81,7 @@ impl Context {
             results,
             strings,
             filenames,
+            donors: Vec::new(),
             generating_resource: false,
         }
     }
@@ -83,6 +90,14 @@ impl Context {
         self.metadata.syscalls()
     }
 
+    pub fn dictionary(&self) -> &Dictionary {
+        self.metadata.dictionary()
+    }
+
+    pub fn file_tree(&self) -> &FileTree {
+        self.metadata.file_tree()
+    }
+
     pub fn results(&self) -> impl Iterator<Item = (&Uuid, &Type)> {
         self.results.iter()
     }
@@ -95,6 +110,32 @@ impl Context {
         &self.filenames
     }
 
+    /// Input buffers of the donors, when the field being mutated is a buffer.
+    pub fn donor_data(&self) -> Vec<Vec<u8>> {
+        self.donors
+            .iter()
+            .filter_map(|arg| match arg {
+                Arg::DataArg(DataArg::In(data)) => Some(data.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Set the arguments used for crossover, i.e. those of the same field in other testcases.
+    pub fn set_donors(&mut self, donors: Vec<Arg>) {
+        self.donors = donors;
+    }
+
+    /// Follow the mutation into a sub-argument, narrowing the donors down to their
+    /// sub-arguments at the same place, and dropping those without one.
+    pub fn narrow_donors<F>(&mut self, f: F)
+    where
+        F: Fn(&Arg) -> Option<&Arg>,
+    {
+        let donors = std::mem::take(&mut self.donors);
+        self.donors = donors.iter().filter_map(|arg| f(arg).cloned()).collect();
+    }
+
     pub fn add_result(&mut self, ty: &Type) -> Uuid {
         let id = Uuid::new_v4();
         self.results.insert(id, ty.clone());
@@ -105,6 +146,7 @@ impl Context {
         self.results.clear();
         self.strings.clear();
         self.filenames.clear();
+        self.donors.clear();
         self.generating_resource = false;
     }
 }
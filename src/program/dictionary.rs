@@ -0,0 +1,51 @@
+//! User-provided dictionaries of interesting tokens.
+
+use std::path::PathBuf;
+
+use libafl::mutators::Tokens;
+
+/// Tokens loaded from dictionary files in AFL format, e.g. `name="value"` or
+/// `"\x01\x00\x00\x00"` for a 32-bit integer.
+#[derive(Debug, Clone, Default)]
+pub struct Dictionary {
+    /// All tokens, used by byte mutation
+    tokens: Vec<Vec<u8>>,
+    /// Tokens that are valid UTF-8 strings without null bytes, used by string generation
+    strings: Vec<String>,
+}
+
+impl Dictionary {
+    /// Create a dictionary from the tokens.
+    pub fn new(tokens: &Tokens) -> Self {
+        let tokens = tokens.tokens().to_vec();
+        let strings = tokens
+            .iter()
+            .filter(|token| !token.contains(&0))
+            .filter_map(|token| String::from_utf8(token.clone()).ok())
+            .collect();
+        Self { tokens, strings }
+    }
+
+    pub fn tokens(&self) -> &[Vec<u8>] {
+        &self.tokens
+    }
+
+    pub fn strings(&self) -> &[String] {
+        &self.strings
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+}
+
+/// Load the tokens from dictionary files in AFL format.
+pub fn load_tokens(paths: &[PathBuf]) -> Tokens {
+    let mut tokens = Tokens::new();
+    for path in paths {
+        tokens
+            .add_from_file(path)
+            .unwrap_or_else(|e| panic!("Failed to load dictionary {path:?}: {e:?}"));
+    }
+    tokens
+}
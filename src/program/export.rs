@@ -0,0 +1,412 @@
+//! Standalone reproducers of inputs, as C or Rust programs for the target.
+//!
+//! The program carries the pointees of all arguments in a static buffer, patching the
+//! pointers in it at startup and the resources after the calls producing them. Calls
+//! are made with raw syscall instructions following the Linux convention of the target,
+//! so it depends on nothing but the compiler.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use uuid::Uuid;
+
+use crate::input::SyscallInput;
+use crate::program::{
+    metadata::{truncate_to_ptr, SyscallMetadata, PAGE_SIZE, PTR_SIZE, VMA_BASE, VMA_PAGES},
+    syscall::{Fixup, Memory, RegValue},
+};
+use crate::trace::format_call;
+
+/// Number of argument registers of raw syscalls.
+const SYSCALL_ARGS: usize = 6;
+
+/// Number of bytes in each line of the buffer.
+const BYTES_PER_LINE: usize = 16;
+
+/// Language of the reproducer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    C,
+    Rust,
+}
+
+/// A call in the program, with the values of its registers.
+struct ExportedCall {
+    nr: u32,
+    regs: Vec<RegValue>,
+    /// Text of the call in comments
+    text: String,
+}
+
+/// Generate the source of a program executing the calls in the input, or an error if
+/// some call cannot be made with raw syscalls.
+pub fn export(
+    input: &SyscallInput,
+    metadata: &SyscallMetadata,
+    language: Language,
+) -> Result<String, String> {
+    let mut memory = Memory::new();
+    let calls: Vec<ExportedCall> = input
+        .calls()
+        .iter()
+        .map(|call| {
+            let syscall = metadata
+                .find_number(call.number())
+                .unwrap_or_else(|| panic!("Unknown syscall number {}", call.number()));
+            let mut regs = memory.place_call(syscall, call)?;
+            assert!(
+                regs.len() <= SYSCALL_ARGS,
+                "Too many arguments for {}",
+                syscall.name()
+            );
+            regs.resize(SYSCALL_ARGS, RegValue::Const(0));
+            Ok(ExportedCall {
+                nr: call.number(),
+                regs,
+                text: format_call(input, call, Some(metadata)),
+            })
+        })
+        .collect::<Result<_, String>>()?;
+
+    // Index of the call producing each result
+    let results: HashMap<Uuid, usize> = input
+        .calls()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, call)| call.result().map(|id| (id, i)))
+        .collect();
+
+    Ok(match language {
+        Language::C => export_c(&calls, &memory, &results),
+        Language::Rust => export_rust(&calls, &memory, &results),
+    })
+}
+
+fn export_c(calls: &[ExportedCall], memory: &Memory, results: &HashMap<Uuid, usize>) -> String {
+    let mut out = header();
+    let size = memory.bytes().len().max(1);
+    writeln!(
+        out,
+        "static unsigned char mem[{size}] __attribute__((aligned(16))) = {{"
+    )
+    .unwrap();
+    out.push_str(&buffer_lines(memory.bytes()));
+    writeln!(out, "}};\n\nstatic long r[{}];\n", calls.len().max(1)).unwrap();
+    out.push_str(C_STORE);
+    out.push('\n');
+    out.push_str(C_SYSCALL);
+    out.push_str("\nint main(void)\n{\n");
+
+    for (offset, fixup) in memory.fixups() {
+        if let Fixup::Pointer(target) = fixup {
+            writeln!(
+                out,
+                "    store(mem + {offset:#x}, (unsigned long)(mem + {target:#x}), {PTR_SIZE});"
+            )
+            .unwrap();
+        }
+    }
+    for (i, call) in calls.iter().enumerate() {
+        let regs: Vec<String> = call
+            .regs
+            .iter()
+            .map(|reg| match reg {
+                RegValue::Const(value) => format!("{:#x}", truncate_to_ptr(*value)),
+                RegValue::Pointer(offset) => format!("(long)(mem + {offset:#x})"),
+                RegValue::Result(id) => result_var(results, *id, i),
+            })
+            .collect();
+        writeln!(out, "\n    // {}", call.text).unwrap();
+        writeln!(
+            out,
+            "    r[{i}] = raw_syscall({}, {});",
+            call.nr,
+            regs.join(", ")
+        )
+        .unwrap();
+        for (offset, size) in result_fixups(memory, results, i) {
+            writeln!(
+                out,
+                "    store(mem + {offset:#x}, (unsigned long)r[{i}], {size});"
+            )
+            .unwrap();
+        }
+    }
+    out.push_str("    return 0;\n}\n");
+    out
+}
+
+fn export_rust(calls: &[ExportedCall], memory: &Memory, results: &HashMap<Uuid, usize>) -> String {
+    let mut out = header();
+    let size = memory.bytes().len().max(1);
+    writeln!(
+        out,
+        "use core::arch::asm;\n\n#[repr(C, align(16))]\nstruct Memory([u8; {size}]);\n\n\
+         static mut MEM: Memory = Memory(["
+    )
+    .unwrap();
+    out.push_str(&buffer_lines(memory.bytes()));
+    writeln!(out, "]);\n").unwrap();
+    out.push_str(RUST_STORE);
+    out.push('\n');
+    out.push_str(RUST_SYSCALL);
+    writeln!(
+        out,
+        "\npub fn main() -> i32 {{\n    let mut r = [0isize; {}];\n    unsafe {{",
+        calls.len().max(1)
+    )
+    .unwrap();
+
+    for (offset, fixup) in memory.fixups() {
+        if let Fixup::Pointer(target) = fixup {
+            writeln!(
+                out,
+                "        store({offset:#x}, addr({target:#x}) as u64, {PTR_SIZE});"
+            )
+            .unwrap();
+        }
+    }
+    for (i, call) in calls.iter().enumerate() {
+        let regs: Vec<String> = call
+            .regs
+            .iter()
+            .map(|reg| match reg {
+                RegValue::Const(value) => format!("{:#x}", truncate_to_ptr(*value)),
+                RegValue::Pointer(offset) => format!("addr({offset:#x})"),
+                RegValue::Result(id) => format!("{} as usize", result_var(results, *id, i)),
+            })
+            .collect();
+        writeln!(out, "\n        // {}", call.text).unwrap();
+        writeln!(
+            out,
+            "        r[{i}] = raw_syscall({}, [{}]);",
+            call.nr,
+            regs.join(", ")
+        )
+        .unwrap();
+        for (offset, size) in result_fixups(memory, results, i) {
+            writeln!(out, "        store({offset:#x}, r[{i}] as u64, {size});").unwrap();
+        }
+    }
+    out.push_str("    }\n    0\n}\n");
+    out
+}
+
+/// Comments at the beginning of the program, the same in C and Rust.
+fn header() -> String {
+    format!(
+        "// Reproducer generated by flicker.\n\
+         // Syscalls are made with the Linux convention of the target, adjust\n\
+         // `raw_syscall` if the kernel uses another one. Arguments in\n\
+         // [{VMA_BASE:#x}, {:#x}) require the region to be mapped, as the harness does.\n\n",
+        VMA_BASE + VMA_PAGES * PAGE_SIZE
+    )
+}
+
+/// Bytes of the buffer in hex, as the lines of an array initializer.
+fn buffer_lines(bytes: &[u8]) -> String {
+    let bytes = if bytes.is_empty() { &[0][..] } else { bytes };
+    bytes
+        .chunks(BYTES_PER_LINE)
+        .map(|line| {
+            let line: Vec<String> = line.iter().map(|byte| format!("{byte:#04x}")).collect();
+            format!("    {},\n", line.join(", "))
+        })
+        .collect()
+}
+
+/// Variable holding the result, or `-1` if it is not produced by a previous call.
+fn result_var(results: &HashMap<Uuid, usize>, id: Uuid, call: usize) -> String {
+    match results.get(&id) {
+        Some(producer) if *producer < call => format!("r[{producer}]"),
+        _ => "-1".to_string(),
+    }
+}
+
+/// Offsets and sizes of the resources in the memory produced by the call.
+fn result_fixups(
+    memory: &Memory,
+    results: &HashMap<Uuid, usize>,
+    call: usize,
+) -> Vec<(usize, usize)> {
+    memory
+        .fixups()
+        .iter()
+        .filter_map(|(offset, fixup)| match fixup {
+            Fixup::Result(id, size) if results.get(id) == Some(&call) => Some((*offset, *size)),
+            _ => None,
+        })
+        .collect()
+}
+
+const C_STORE: &str = r#"static void store(unsigned char *p, unsigned long v, int n)
+{
+    for (int i = 0; i < n; i++)
+        p[i] = (unsigned char)(v >> (8 * i));
+}
+"#;
+
+const RUST_STORE: &str = r#"unsafe fn addr(offset: usize) -> usize {
+    (core::ptr::addr_of_mut!(MEM.0) as *mut u8).add(offset) as usize
+}
+
+unsafe fn store(offset: usize, value: u64, size: usize) {
+    let mem = core::ptr::addr_of_mut!(MEM.0) as *mut u8;
+    for i in 0..size {
+        *mem.add(offset + i) = (value >> (8 * i)) as u8;
+    }
+}
+"#;
+
+#[cfg(any(feature = "riscv64", feature = "riscv32"))]
+const C_SYSCALL: &str = r#"static long raw_syscall(long nr, long a0, long a1, long a2, long a3, long a4, long a5)
+{
+    register long x10 __asm__("a0") = a0;
+    register long x11 __asm__("a1") = a1;
+    register long x12 __asm__("a2") = a2;
+    register long x13 __asm__("a3") = a3;
+    register long x14 __asm__("a4") = a4;
+    register long x15 __asm__("a5") = a5;
+    register long x17 __asm__("a7") = nr;
+    __asm__ volatile("ecall"
+                     : "+r"(x10)
+                     : "r"(x11), "r"(x12), "r"(x13), "r"(x14), "r"(x15), "r"(x17)
+                     : "memory");
+    return x10;
+}
+"#;
+
+#[cfg(any(feature = "riscv64", feature = "riscv32"))]
+const RUST_SYSCALL: &str = r#"unsafe fn raw_syscall(nr: usize, args: [usize; 6]) -> isize {
+    let ret: usize;
+    asm!(
+        "ecall",
+        inlateout("a0") args[0] => ret,
+        in("a1") args[1],
+        in("a2") args[2],
+        in("a3") args[3],
+        in("a4") args[4],
+        in("a5") args[5],
+        in("a7") nr,
+        options(nostack),
+    );
+    ret as isize
+}
+"#;
+
+#[cfg(feature = "x86_64")]
+const C_SYSCALL: &str = r#"static long raw_syscall(long nr, long a0, long a1, long a2, long a3, long a4, long a5)
+{
+    register long r10 __asm__("r10") = a3;
+    register long r8 __asm__("r8") = a4;
+    register long r9 __asm__("r9") = a5;
+    long ret;
+    __asm__ volatile("syscall"
+                     : "=a"(ret)
+                     : "a"(nr), "D"(a0), "S"(a1), "d"(a2), "r"(r10), "r"(r8), "r"(r9)
+                     : "rcx", "r11", "memory");
+    return ret;
+}
+"#;
+
+#[cfg(feature = "x86_64")]
+const RUST_SYSCALL: &str = r#"unsafe fn raw_syscall(nr: usize, args: [usize; 6]) -> isize {
+    let ret: usize;
+    asm!(
+        "syscall",
+        inlateout("rax") nr => ret,
+        in("rdi") args[0],
+        in("rsi") args[1],
+        in("rdx") args[2],
+        in("r10") args[3],
+        in("r8") args[4],
+        in("r9") args[5],
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack),
+    );
+    ret as isize
+}
+"#;
+
+#[cfg(feature = "i386")]
+const C_SYSCALL: &str = r#"static long raw_syscall(long nr, long a0, long a1, long a2, long a3, long a4, long a5)
+{
+    // ebp may be the frame pointer, so the last argument is loaded from memory
+    static long arg5;
+    long ret;
+    arg5 = a5;
+    __asm__ volatile("push %%ebp\n\t"
+                     "mov %7, %%ebp\n\t"
+                     "int $0x80\n\t"
+                     "pop %%ebp"
+                     : "=a"(ret)
+                     : "a"(nr), "b"(a0), "c"(a1), "d"(a2), "S"(a3), "D"(a4), "m"(arg5)
+                     : "memory");
+    return ret;
+}
+"#;
+
+#[cfg(feature = "i386")]
+const RUST_SYSCALL: &str = r#"unsafe fn raw_syscall(nr: usize, args: [usize; 6]) -> isize {
+    let ret: usize;
+    // ebx, esi and ebp are reserved by the compiler, so they are saved
+    // and loaded from the arguments in memory
+    asm!(
+        "push ebp",
+        "push esi",
+        "push ebx",
+        "mov ebx, [edi]",
+        "mov ecx, [edi + 4]",
+        "mov edx, [edi + 8]",
+        "mov esi, [edi + 12]",
+        "mov ebp, [edi + 20]",
+        "mov edi, [edi + 16]",
+        "int 0x80",
+        "pop ebx",
+        "pop esi",
+        "pop ebp",
+        inlateout("eax") nr => ret,
+        inout("edi") args.as_ptr() => _,
+        lateout("ecx") _,
+        lateout("edx") _,
+    );
+    ret as isize
+}
+"#;
+
+#[cfg(feature = "aarch64")]
+const C_SYSCALL: &str = r#"static long raw_syscall(long nr, long a0, long a1, long a2, long a3, long a4, long a5)
+{
+    register long x0 __asm__("x0") = a0;
+    register long x1 __asm__("x1") = a1;
+    register long x2 __asm__("x2") = a2;
+    register long x3 __asm__("x3") = a3;
+    register long x4 __asm__("x4") = a4;
+    register long x5 __asm__("x5") = a5;
+    register long x8 __asm__("x8") = nr;
+    __asm__ volatile("svc #0"
+                     : "+r"(x0)
+                     : "r"(x1), "r"(x2), "r"(x3), "r"(x4), "r"(x5), "r"(x8)
+                     : "memory");
+    return x0;
+}
+"#;
+
+#[cfg(feature = "aarch64")]
+const RUST_SYSCALL: &str = r#"unsafe fn raw_syscall(nr: usize, args: [usize; 6]) -> isize {
+    let ret: usize;
+    asm!(
+        "svc #0",
+        inlateout("x0") args[0] => ret,
+        in("x1") args[1],
+        in("x2") args[2],
+        in("x3") args[3],
+        in("x4") args[4],
+        in("x5") args[5],
+        in("x8") nr,
+        options(nostack),
+    );
+    ret as isize
+}
+"#;
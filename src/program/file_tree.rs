@@ -0,0 +1,118 @@
+//! Model of the file system on the guest, used to generate filenames.
+//!
+//! The file tree is described by a text file, one entry per line:
+//!
+//! ```text
+//! # Comments start with `#`
+//! /bin/                  # Directories end with `/`
+//! /bin/sh                # Regular files
+//! /lib/libc.so -> /lib/libc.so.6  # Symbolic links
+//! @path_max 4096         # Max length of paths, `PATH_MAX` in Linux
+//! @name_max 255          # Max length of file names, `NAME_MAX` in Linux
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+/// Default max length of paths, following Linux.
+const DEFAULT_PATH_MAX: u64 = 4096;
+
+/// Default max length of file names, following Linux.
+const DEFAULT_NAME_MAX: u64 = 255;
+
+/// Files, directories and symbolic links pre-created on the guest,
+/// and the limits of paths of the kernel.
+#[derive(Debug, Clone)]
+pub struct FileTree {
+    dirs: Vec<String>,
+    files: Vec<String>,
+    /// Symbolic links and their targets
+    symlinks: Vec<(String, String)>,
+    path_max: u64,
+    name_max: u64,
+}
+
+impl Default for FileTree {
+    /// Only the root and the current directory are known to exist.
+    fn default() -> Self {
+        Self {
+            dirs: vec!["/".to_string(), ".".to_string()],
+            files: Vec::new(),
+            symlinks: Vec::new(),
+            path_max: DEFAULT_PATH_MAX,
+            name_max: DEFAULT_NAME_MAX,
+        }
+    }
+}
+
+impl FileTree {
+    /// Load the file tree from a description file. The root and the current
+    /// directory are always included.
+    pub fn from_file(path: &Path) -> Self {
+        let text = fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Failed to read file tree {path:?}: {e}"));
+        Self::parse(&text)
+    }
+
+    fn parse(text: &str) -> Self {
+        let mut tree = Self::default();
+        for (lineno, line) in text.lines().enumerate() {
+            let line = line.split_once('#').map_or(line, |(entry, _)| entry).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(directive) = line.strip_prefix('@') {
+                let (key, value) = directive
+                    .split_once(char::is_whitespace)
+                    .unwrap_or_else(|| panic!("Missing value at line {}", lineno + 1));
+                let value = value.trim().parse().unwrap_or_else(|e| {
+                    panic!("Invalid value {value:?} at line {}: {e}", lineno + 1)
+                });
+                match key {
+                    "path_max" => tree.path_max = value,
+                    "name_max" => tree.name_max = value,
+                    _ => panic!("Unknown directive {key:?} at line {}", lineno + 1),
+                }
+            } else if let Some((link, target)) = line.split_once("->") {
+                tree.symlinks
+                    .push((link.trim().to_string(), target.trim().to_string()));
+            } else if line.len() > 1 && line.ends_with('/') {
+                tree.dirs.push(line.trim_end_matches('/').to_string());
+            } else if line != "/" {
+                tree.files.push(line.to_string());
+            }
+        }
+        tree.dirs.sort();
+        tree.dirs.dedup();
+        tree
+    }
+
+    pub fn dirs(&self) -> &[String] {
+        &self.dirs
+    }
+
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    pub fn symlinks(&self) -> &[(String, String)] {
+        &self.symlinks
+    }
+
+    pub fn path_max(&self) -> u64 {
+        self.path_max
+    }
+
+    pub fn name_max(&self) -> u64 {
+        self.name_max
+    }
+
+    /// All existing paths, including directories, files and symbolic links.
+    pub fn paths(&self) -> impl Iterator<Item = &String> {
+        self.dirs
+            .iter()
+            .chain(self.files.iter())
+            .chain(self.symlinks.iter().map(|(link, _)| link))
+    }
+}
@@ -1,21 +1,171 @@
 use syzlang_parser::parser::{Arch, Parsed};
 
-use crate::program::syscall::Syscall;
+use crate::program::{dictionary::Dictionary, file_tree::FileTree, syscall::Syscall};
+
+#[cfg(not(any(
+    feature = "riscv64",
+    feature = "riscv32",
+    feature = "x86_64",
+    feature = "i386",
+    feature = "aarch64"
+)))]
+compile_error!(
+    "No target architecture selected, enable one of `riscv64`, `riscv32`, `x86_64`, \
+     `i386` or `aarch64`"
+);
+
+#[cfg(any(
+    all(feature = "riscv64", feature = "riscv32"),
+    all(feature = "riscv64", feature = "x86_64"),
+    all(feature = "riscv64", feature = "i386"),
+    all(feature = "riscv64", feature = "aarch64"),
+    all(feature = "riscv32", feature = "x86_64"),
+    all(feature = "riscv32", feature = "i386"),
+    all(feature = "riscv32", feature = "aarch64"),
+    all(feature = "x86_64", feature = "i386"),
+    all(feature = "x86_64", feature = "aarch64"),
+    all(feature = "i386", feature = "aarch64"),
+))]
+compile_error!("Multiple target architectures selected, enable only one of them");
 
 #[cfg(feature = "riscv64")]
 pub const ARCH: Arch = Arch::Riscv64;
 
+// riscv32 has no syscall table of its own in syzlang, so it takes the numbers of
+// riscv64. This is only an approximation of the rv32 asm-generic table: calls with
+// 32-bit `time_t` (e.g. `clock_gettime`, `nanosleep`) do not exist and are replaced by
+// the `*_time64` ones numbered from 403, `fstat` and `newfstatat` do not exist, and
+// some numbers are taken by the 64-bit offset versions (e.g. `mmap` is `mmap2`).
+// Descriptions for riscv32 have to avoid such calls, or describe the rv32 ones with
+// their numbers given for riscv64 in the constants. Note that sizes must not be
+// evaluated with `ARCH` directly, use `PTR_SIZE` instead.
+#[cfg(feature = "riscv32")]
+pub const ARCH: Arch = Arch::Riscv64;
+
 #[cfg(feature = "x86_64")]
 pub const ARCH: Arch = Arch::X86_64;
 
+#[cfg(feature = "i386")]
+pub const ARCH: Arch = Arch::X86;
+
+#[cfg(feature = "aarch64")]
+pub const ARCH: Arch = Arch::Aarch64;
+
+/// Size of a pointer (and `intptr`) on the target, in bytes.
+#[cfg(any(feature = "riscv64", feature = "x86_64", feature = "aarch64"))]
+pub const PTR_SIZE: usize = 8;
+
+/// Size of a pointer (and `intptr`) on the target, in bytes.
+#[cfg(any(feature = "riscv32", feature = "i386"))]
+pub const PTR_SIZE: usize = 4;
+
+/// Size of a page on the target, in bytes.
+pub const PAGE_SIZE: u64 = 0x1000;
+
+/// Addresses likely to be inside the kernel, used as special pointer values.
+/// Teaching kernels usually identity-map the kernel image at 0x80000000 or
+/// 0x80200000, so these are included besides the higher-half kernel base.
+#[cfg(feature = "riscv64")]
+pub const KERNEL_ADDRESSES: &[u64] = &[0xffff_ffc0_0000_0000, 0x8000_0000, 0x8020_0000];
+
+/// Addresses likely to be inside the kernel, used as special pointer values.
+#[cfg(feature = "riscv32")]
+pub const KERNEL_ADDRESSES: &[u64] = &[0xc000_0000, 0x8000_0000, 0x8040_0000];
+
+/// Addresses likely to be inside the kernel, used as special pointer values.
+#[cfg(feature = "x86_64")]
+pub const KERNEL_ADDRESSES: &[u64] = &[0xffff_ffff_8000_0000, 0xffff_8000_0000_0000];
+
+/// Addresses likely to be inside the kernel, used as special pointer values.
+#[cfg(feature = "i386")]
+pub const KERNEL_ADDRESSES: &[u64] = &[0xc000_0000, 0x8000_0000, 0x8010_0000];
+
+/// Addresses likely to be inside the kernel, used as special pointer values.
+#[cfg(feature = "aarch64")]
+pub const KERNEL_ADDRESSES: &[u64] = &[0xffff_0000_0000_0000, 0xffff_8000_0000_0000];
+
+/// Base address of the region mapped by the harness for `vma` arguments.
+/// The harness has to map `[VMA_BASE, VMA_BASE + VMA_PAGES * PAGE_SIZE)`
+/// as readable and writable before executing any calls.
+pub const VMA_BASE: u64 = 0x2000_0000;
+
+/// Number of pages in the region mapped by the harness for `vma` arguments.
+pub const VMA_PAGES: u64 = 0x100;
+
+/// Byte order of the target. All supported targets are little-endian for now.
+///
+/// It applies to the memory representations of arguments, i.e. the layout, byte
+/// mutations and checksums, but not to the exec encoding: integers are sent as
+/// postcard varints, which the harness decodes into its own byte order.
+pub const ENDIAN: Endian = Endian::Little;
+
+/// Byte order of a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    /// Encode the lowest `size` bytes of `value` in this byte order.
+    pub fn encode(self, value: u64, size: usize) -> Vec<u8> {
+        assert!(size <= 8, "Integer size {size} too large");
+        match self {
+            Endian::Little => value.to_le_bytes()[..size].to_vec(),
+            Endian::Big => value.to_be_bytes()[8 - size..].to_vec(),
+        }
+    }
+
+    /// Decode an integer of at most 8 bytes in this byte order.
+    pub fn decode(self, bytes: &[u8]) -> u64 {
+        let size = bytes.len();
+        assert!(size <= 8, "Integer size {size} too large");
+        let mut buf = [0; 8];
+        match self {
+            Endian::Little => {
+                buf[..size].copy_from_slice(bytes);
+                u64::from_le_bytes(buf)
+            }
+            Endian::Big => {
+                buf[8 - size..].copy_from_slice(bytes);
+                u64::from_be_bytes(buf)
+            }
+        }
+    }
+}
+
+/// Truncate a value to the pointer width of the target.
+#[inline]
+pub fn truncate_to_ptr(value: u64) -> u64 {
+    truncate_to_bits(value, (PTR_SIZE * 8) as u8)
+}
+
+/// Truncate a value to the given number of bits.
+#[inline]
+pub fn truncate_to_bits(value: u64, bits: u8) -> u64 {
+    if bits >= 64 {
+        value
+    } else {
+        value & ((1 << bits) - 1)
+    }
+}
+
 /// Metadata for syscalls.
 #[derive(Debug, Clone)]
-pub struct SyscallMetadata(Vec<Syscall>);
+pub struct SyscallMetadata {
+    syscalls: Vec<Syscall>,
+    dictionary: Dictionary,
+    file_tree: FileTree,
+}
 
 impl SyscallMetadata {
     /// Create a new `SyscallMetadata` from a list of syscalls.
     pub fn new(syscalls: Vec<Syscall>) -> Self {
-        Self(syscalls)
+        Self {
+            syscalls,
+            dictionary: Dictionary::default(),
+            file_tree: FileTree::default(),
+        }
     }
 
     /// Create a new `SyscallMetadata` from a parsed syzlang file.
@@ -30,15 +180,38 @@ impl SyscallMetadata {
         Self::new(syscalls)
     }
 
+    /// Use the tokens in the dictionary for generation and mutation.
+    pub fn with_dictionary(mut self, dictionary: Dictionary) -> Self {
+        self.dictionary = dictionary;
+        self
+    }
+
+    /// Use the file tree of the guest to generate filenames.
+    pub fn with_file_tree(mut self, file_tree: FileTree) -> Self {
+        self.file_tree = file_tree;
+        self
+    }
+
     pub fn syscalls(&self) -> &[Syscall] {
-        &self.0
+        &self.syscalls
+    }
+
+    pub fn dictionary(&self) -> &Dictionary {
+        &self.dictionary
+    }
+
+    pub fn file_tree(&self) -> &FileTree {
+        &self.file_tree
     }
 
     pub fn find_number(&self, nr: u32) -> Option<&Syscall> {
-        self.0.iter().find(|s| s.number() == nr)
+        self.syscalls.iter().find(|s| s.number() == nr)
     }
 }
 
+/// Find the syscall number for the target arch.
+/// Falls back to the arch-independent number, which is used by the architectures
+/// sharing the generic syscall table (e.g. riscv64 and aarch64).
 fn find_sysno_with_default(parsed: &Parsed, name: &str) -> u32 {
     if let Some(nr) = parsed.consts().find_sysno(&name, &ARCH) {
         // Use the syscall number for the arch if available
@@ -1,4 +1,7 @@
 pub(crate) mod call;
 pub mod context;
+pub mod dictionary;
+pub mod export;
+pub mod file_tree;
 pub mod metadata;
 pub(crate) mod syscall;
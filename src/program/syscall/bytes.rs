@@ -0,0 +1,290 @@
+//! Mutation of raw bytes in buffers.
+//!
+//! The mutations resemble the havoc stage of AFL, plus crossover with other buffers
+//! and insertion of dictionary tokens. All randomness comes from the caller's RNG,
+//! so the results are reproducible under a fixed seed.
+
+use libafl_bolts::{nonzero, rands::Rand};
+
+use super::generation::MAX_BUFFER_LENGTH;
+use crate::program::metadata::ENDIAN;
+use crate::utility::*;
+
+const INTERESTING_8: [i8; 9] = [-128, -1, 0, 1, 16, 32, 64, 100, 127];
+const INTERESTING_16: [i16; 10] = [-32768, -129, 128, 255, 256, 512, 1000, 1024, 4096, 32767];
+const INTERESTING_32: [i32; 8] = [
+    -2147483648,
+    -100663046,
+    -32769,
+    32768,
+    65535,
+    65536,
+    100663045,
+    2147483647,
+];
+
+/// Max value added to or subtracted from integers in arithmetic mutations.
+const ARITH_MAX: u64 = 35;
+
+/// Kinds of byte mutations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteMutation {
+    BitFlip,
+    ByteRand,
+    Interesting,
+    Arith,
+    Delete,
+    Insert,
+    Set,
+    Copy,
+    Swap,
+    Token,
+    Crossover,
+}
+
+impl ByteMutation {
+    const ALL: [ByteMutation; 11] = [
+        ByteMutation::BitFlip,
+        ByteMutation::ByteRand,
+        ByteMutation::Interesting,
+        ByteMutation::Arith,
+        ByteMutation::Delete,
+        ByteMutation::Insert,
+        ByteMutation::Set,
+        ByteMutation::Copy,
+        ByteMutation::Swap,
+        ByteMutation::Token,
+        ByteMutation::Crossover,
+    ];
+}
+
+/// Byte mutation engine. It holds no state besides references to the sources of
+/// external data, so creating one for each mutation is cheap.
+#[derive(Debug, Clone, Copy)]
+pub struct ByteMutator<'a> {
+    /// Allowed range of the length
+    range: Option<(u64, u64)>,
+    /// Tokens from the dictionary
+    tokens: &'a [Vec<u8>],
+    /// Buffers of the same field from other testcases, for crossover
+    donors: &'a [Vec<u8>],
+}
+
+impl<'a> ByteMutator<'a> {
+    pub fn new(range: Option<(u64, u64)>, tokens: &'a [Vec<u8>], donors: &'a [Vec<u8>]) -> Self {
+        Self {
+            range,
+            tokens,
+            donors,
+        }
+    }
+
+    /// Max length of the mutated bytes.
+    fn max_len(&self) -> usize {
+        let max = self.range.map_or(MAX_BUFFER_LENGTH, |(_, max)| max);
+        max.min(MAX_BUFFER_LENGTH) as usize
+    }
+
+    /// Mutate the bytes by stacking random mutations, and ensure the length is in range.
+    pub fn mutate<R: Rand>(&self, rand: &mut R, bytes: &mut Vec<u8>) {
+        if self.max_len() == 0 {
+            // Nothing can be done
+            bytes.clear();
+            return;
+        }
+
+        loop {
+            let kind = ByteMutation::ALL[below(rand, ByteMutation::ALL.len())];
+            if self.apply(rand, bytes, kind) && one_of(rand, 3) {
+                break;
+            }
+        }
+
+        // Resize if it can not fitted into the range
+        if let Some((min, _)) = self.range {
+            if (bytes.len() as u64) < min {
+                bytes.resize(min as usize, 0);
+            }
+        }
+        bytes.truncate(self.max_len());
+    }
+
+    /// Apply a single mutation, returning whether the bytes are mutated.
+    fn apply<R: Rand>(&self, rand: &mut R, bytes: &mut Vec<u8>, kind: ByteMutation) -> bool {
+        let len = bytes.len();
+        match kind {
+            ByteMutation::BitFlip => {
+                if len == 0 {
+                    return false;
+                }
+                let pos = below(rand, len);
+                bytes[pos] ^= 1 << rand.below(nonzero!(8));
+            }
+            ByteMutation::ByteRand => {
+                if len == 0 {
+                    return false;
+                }
+                let pos = below(rand, len);
+                // Make sure the byte is changed
+                bytes[pos] ^= rand.between(1, 255) as u8;
+            }
+            ByteMutation::Interesting => {
+                let (val, size) = match rand.below(nonzero!(3)) {
+                    0 => (choose(rand, &INTERESTING_8) as u64, 1),
+                    1 => (choose(rand, &INTERESTING_16) as u64, 2),
+                    _ => (choose(rand, &INTERESTING_32) as u64, 4),
+                };
+                return overwrite(rand, bytes, &ENDIAN.encode(val, size));
+            }
+            ByteMutation::Arith => {
+                let size = [1, 2, 4, 8][rand.below(nonzero!(4))];
+                if len < size {
+                    return false;
+                }
+                let pos = below(rand, len - size + 1);
+                let delta = rand.between(1, ARITH_MAX as usize) as u64;
+                let val = ENDIAN.decode(&bytes[pos..pos + size]);
+                let val = if binary(rand) {
+                    val.wrapping_add(delta)
+                } else {
+                    val.wrapping_sub(delta)
+                };
+                bytes[pos..pos + size].copy_from_slice(&ENDIAN.encode(val, size));
+            }
+            ByteMutation::Delete => {
+                let min = self.range.map_or(0, |(min, _)| min as usize);
+                if len <= min {
+                    return false;
+                }
+                let count = self.rand_count(rand, len - min);
+                let pos = below(rand, len - count + 1);
+                bytes.drain(pos..pos + count);
+            }
+            ByteMutation::Insert => {
+                let Some(room) = self.room(len) else {
+                    return false;
+                };
+                let count = self.rand_count(rand, room);
+                let pos = below(rand, len + 1);
+                let inserted: Vec<u8> = if binary(rand) {
+                    // Repeat a byte
+                    let byte = rand.below(nonzero!(256)) as u8;
+                    vec![byte; count]
+                } else {
+                    (0..count)
+                        .map(|_| rand.below(nonzero!(256)) as u8)
+                        .collect()
+                };
+                bytes.splice(pos..pos, inserted);
+            }
+            ByteMutation::Set => {
+                if len == 0 {
+                    return false;
+                }
+                let count = self.rand_count(rand, len);
+                let pos = below(rand, len - count + 1);
+                let byte = rand.below(nonzero!(256)) as u8;
+                bytes[pos..pos + count].fill(byte);
+            }
+            ByteMutation::Copy => {
+                if len < 2 {
+                    return false;
+                }
+                let count = self.rand_count(rand, len - 1);
+                let from = below(rand, len - count + 1);
+                let chunk = bytes[from..from + count].to_vec();
+                if binary(rand) {
+                    // Copy to another position
+                    return overwrite(rand, bytes, &chunk);
+                }
+                // Insert a copy
+                let Some(room) = self.room(len) else {
+                    return false;
+                };
+                let pos = below(rand, len + 1);
+                bytes.splice(pos..pos, chunk.into_iter().take(room));
+            }
+            ByteMutation::Swap => {
+                if len < 2 {
+                    return false;
+                }
+                let count = self.rand_count(rand, len / 2);
+                let first = below(rand, len - 2 * count + 1);
+                let second = rand.between(first + count, len - count);
+                let (head, tail) = bytes.split_at_mut(second);
+                head[first..first + count].swap_with_slice(&mut tail[..count]);
+            }
+            ByteMutation::Token => {
+                if self.tokens.is_empty() {
+                    return false;
+                }
+                let token = &self.tokens[below(rand, self.tokens.len())];
+                return self.put(rand, bytes, token);
+            }
+            ByteMutation::Crossover => {
+                if self.donors.is_empty() {
+                    return false;
+                }
+                let donor = &self.donors[below(rand, self.donors.len())];
+                if donor.is_empty() {
+                    return false;
+                }
+                // Take a random chunk of the donor
+                let count = self.rand_count(rand, donor.len());
+                let from = below(rand, donor.len() - count + 1);
+                return self.put(rand, bytes, &donor[from..from + count]);
+            }
+        }
+        true
+    }
+
+    /// Insert or overwrite the chunk at a random position.
+    fn put<R: Rand>(&self, rand: &mut R, bytes: &mut Vec<u8>, chunk: &[u8]) -> bool {
+        if binary(rand) && overwrite(rand, bytes, chunk) {
+            return true;
+        }
+        let Some(room) = self.room(bytes.len()) else {
+            return false;
+        };
+        let pos = below(rand, bytes.len() + 1);
+        bytes.splice(pos..pos, chunk.iter().copied().take(room));
+        true
+    }
+
+    /// Number of bytes that can be inserted, if any.
+    fn room(&self, len: usize) -> Option<usize> {
+        self.max_len().checked_sub(len).filter(|room| *room > 0)
+    }
+
+    /// Random number of bytes to operate on, in `[1, max]`, preferring small ones.
+    fn rand_count<R: Rand>(&self, rand: &mut R, max: usize) -> usize {
+        let max = if n_out_of(rand, 3, 4) {
+            max.min(16)
+        } else {
+            max
+        };
+        rand.between(1, max)
+    }
+}
+
+/// Overwrite the bytes with the chunk at a random position, if it fits.
+fn overwrite<R: Rand>(rand: &mut R, bytes: &mut [u8], chunk: &[u8]) -> bool {
+    if chunk.is_empty() || chunk.len() > bytes.len() {
+        return false;
+    }
+    let pos = below(rand, bytes.len() - chunk.len() + 1);
+    if bytes[pos..pos + chunk.len()] == *chunk {
+        return false;
+    }
+    bytes[pos..pos + chunk.len()].copy_from_slice(chunk);
+    true
+}
+
+fn choose<R: Rand, T: Copy>(rand: &mut R, values: &[T]) -> T {
+    values[below(rand, values.len())]
+}
+
+/// Random number in `[0, n)`, where `n` is ensured to be positive by the caller.
+fn below<R: Rand>(rand: &mut R, n: usize) -> usize {
+    rand.below(n.try_into().unwrap())
+}
@@ -0,0 +1,165 @@
+//! Conditions of struct fields, e.g. `field int32 (if[value[flags] & FLAG])`.
+//!
+//! A conditional field is present only if its condition evaluates to non-zero
+//! against the values of its sibling fields. Like syzkaller, it is represented
+//! as a union of the value and `void` in the argument tree.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use syzlang_parser::parser::Parsed;
+
+use super::const_to_u64;
+
+/// Operators in conditions, from the lowest precedence to the highest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Or,
+    Eq,
+    Ne,
+    And,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    /// Integer literal or resolved constant
+    Const(u64),
+    /// Value of a sibling field, `value[name]`
+    Value(String),
+    /// Binary operation
+    Binary(Operator, Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    /// Parse a condition expression. Identifiers are resolved as constants.
+    pub fn parse(expr: &str, ctx: &Parsed) -> Self {
+        let mut parser = ConditionParser {
+            chars: expr.chars().peekable(),
+            ctx,
+        };
+        let cond = parser.parse_or();
+        parser.skip_whitespace();
+        assert!(
+            parser.chars.peek().is_none(),
+            "Trailing characters in condition: {expr}"
+        );
+        cond
+    }
+
+    /// Evaluate the condition, getting the values of sibling fields from `lookup`.
+    /// Missing values (e.g. absent or non-integer fields) are considered to be zero.
+    pub fn evaluate<F>(&self, lookup: &F) -> u64
+    where
+        F: Fn(&str) -> Option<u64>,
+    {
+        match self {
+            Condition::Const(val) => *val,
+            Condition::Value(name) => lookup(name).unwrap_or(0),
+            Condition::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.evaluate(lookup), rhs.evaluate(lookup));
+                match op {
+                    Operator::Or => (lhs != 0 || rhs != 0) as u64,
+                    Operator::Eq => (lhs == rhs) as u64,
+                    Operator::Ne => (lhs != rhs) as u64,
+                    Operator::And => lhs & rhs,
+                }
+            }
+        }
+    }
+}
+
+struct ConditionParser<'a> {
+    chars: Peekable<Chars<'a>>,
+    ctx: &'a Parsed,
+}
+
+impl ConditionParser<'_> {
+    fn parse_or(&mut self) -> Condition {
+        let mut lhs = self.parse_cmp();
+        while self.eat("||") {
+            let rhs = self.parse_cmp();
+            lhs = Condition::Binary(Operator::Or, Box::new(lhs), Box::new(rhs));
+        }
+        lhs
+    }
+
+    fn parse_cmp(&mut self) -> Condition {
+        let lhs = self.parse_and();
+        let op = if self.eat("==") {
+            Operator::Eq
+        } else if self.eat("!=") {
+            Operator::Ne
+        } else {
+            return lhs;
+        };
+        let rhs = self.parse_and();
+        Condition::Binary(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    fn parse_and(&mut self) -> Condition {
+        let mut lhs = self.parse_primary();
+        while self.eat("&") {
+            let rhs = self.parse_primary();
+            lhs = Condition::Binary(Operator::And, Box::new(lhs), Box::new(rhs));
+        }
+        lhs
+    }
+
+    fn parse_primary(&mut self) -> Condition {
+        if self.eat("(") {
+            let cond = self.parse_or();
+            assert!(self.eat(")"), "Unclosed parenthesis in condition");
+            return cond;
+        }
+
+        self.skip_whitespace();
+        let word: String = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        assert!(!word.is_empty(), "Expected operand in condition");
+
+        if word == "value" {
+            assert!(self.eat("["), "Expected `[` after `value`");
+            self.skip_whitespace();
+            let name = self.take_while(|c| c != ']' && !c.is_whitespace());
+            assert!(self.eat("]"), "Unclosed `value[` in condition");
+            return Condition::Value(name);
+        }
+
+        let val = if let Some(hex) = word.strip_prefix("0x") {
+            u64::from_str_radix(hex, 16).ok()
+        } else if word.starts_with(|c: char| c.is_ascii_digit()) {
+            word.parse().ok()
+        } else {
+            const_to_u64(&word, self.ctx)
+        };
+        Condition::Const(val.unwrap_or_else(|| panic!("Invalid operand in condition: {word}")))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.chars.next_if(|c| pred(*c)) {
+            word.push(c);
+        }
+        word
+    }
+
+    /// Consume the given token if it is next, ignoring whitespaces before it.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let mut lookahead = self.chars.clone();
+        for expected in token.chars() {
+            if lookahead.next() != Some(expected) {
+                return false;
+            }
+        }
+        // Don't take `&` from `&&` or `|` from `||`
+        if (token == "&" || token == "|") && lookahead.peek() == token.chars().next().as_ref() {
+            return false;
+        }
+        self.chars = lookahead;
+        true
+    }
+}
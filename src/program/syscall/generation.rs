@@ -1,20 +1,28 @@
 use std::iter;
+use std::num::NonZeroUsize;
 use std::ops::Neg;
 
 use libafl_bolts::{nonzero, rands::Rand};
 
 use enum_dispatch::enum_dispatch;
+use enum_downcast::EnumDowncast;
 use log::debug;
 use uuid::Uuid;
 
+use super::layout::update_computed_fields;
 use super::{
-    ArrayType, ByteBuffer, Direction, Field, FilenameBuffer, FlagType, IntType, PointerType,
-    ResourceType, StringBuffer, StructType, UnionType,
+    ArrayType, ByteBuffer, ConstType, CsumKind, CsumType, Direction, Field, FilenameBuffer,
+    FlagType, IntType, LenType, PointerType, ProcType, ResourceType, StringBuffer, StructType,
+    Type, UnionType, VmaType, CONDITION_ABSENT, CONDITION_PRESENT,
 };
 use crate::generator::{generate_arg, generate_args, generate_call};
 use crate::program::{
-    call::{Arg, Call, ConstArg, DataArg, GroupArg, PointerArg, ResultArg},
+    call::{Arg, Call, ConstArg, DataArg, GroupArg, PointerArg, ResultArg, UnionArg},
     context::Context,
+    file_tree::FileTree,
+    metadata::{
+        truncate_to_bits, Endian, ENDIAN, KERNEL_ADDRESSES, PAGE_SIZE, VMA_BASE, VMA_PAGES,
+    },
 };
 use crate::utility::*;
 
@@ -34,11 +42,12 @@ pub trait GenerateArg {
 
 impl GenerateArg for Field {
     fn generate<R: Rand>(&self, rand: &mut R, ctx: &mut Context) -> (Arg, Vec<Call>) {
-        self.ty.generate(rand, ctx)
+        let (arg, calls) = self.ty.generate(rand, ctx);
+        (self.present(arg), calls)
     }
 
     fn default(&self) -> Arg {
-        self.ty.default()
+        self.present(self.ty.default())
     }
 }
 
@@ -126,7 +135,7 @@ impl FlagType {
 
 impl GenerateArg for FlagType {
     fn generate<R: Rand>(&self, rand: &mut R, _ctx: &mut Context) -> (Arg, Vec<Call>) {
-        let val = self.generate_impl(rand, 0);
+        let val = truncate_to_bits(self.generate_impl(rand, 0), self.bits);
         (ConstArg::new(val).into(), vec![])
     }
 
@@ -171,9 +180,12 @@ impl GenerateArg for ArrayType {
 impl GenerateArg for PointerType {
     fn generate<R: Rand>(&self, rand: &mut R, ctx: &mut Context) -> (Arg, Vec<Call>) {
         // The resource we are trying to generate may be in the pointer,
-        // so don't try to create an empty special pointer during resource generation.
-        if !ctx.generating_resource && one_of(rand, 1000) {
-            (PointerArg::from_addr(0).into(), vec![])
+        // so don't try to create a special pointer during resource generation.
+        if !ctx.generating_resource && one_of(rand, 100) {
+            (
+                PointerArg::from_addr(rand_special_pointer(rand)).into(),
+                vec![],
+            )
         } else {
             let (arg, calls) = generate_arg(rand, ctx, &self.elem);
             (PointerArg::from_res(arg).into(), calls)
@@ -190,6 +202,10 @@ impl StringBuffer {
         let mut string = if !self.values.is_empty() {
             // Choose a special value
             self.values[rand.below(self.values.len().try_into().unwrap())].clone()
+        } else if !ctx.dictionary().strings().is_empty() && one_of(rand, 3) {
+            // Use a token from the dictionary
+            let strings = ctx.dictionary().strings();
+            strings[rand.below(strings.len().try_into().unwrap())].clone()
         } else if binary(rand) {
             // Use existing strings
             sample_from_iter(rand, ctx.strings().iter())
@@ -251,9 +267,14 @@ impl FilenameBuffer {
             // Use a special filename
             SPECIAL_FILENAMES[rand.below(nonzero!(SPECIAL_FILENAMES.len()))].to_string()
         } else if n_out_of(rand, 9, 10) {
-            // Use an existing filename
-            sample_from_iter(rand, ctx.filenames().iter())
-                .cloned()
+            // Use an existing filename, either generated before or pre-created on the guest
+            let existing = if binary(rand) {
+                sample_from_iter(rand, ctx.filenames().iter())
+            } else {
+                sample_from_iter(rand, ctx.file_tree().paths())
+            };
+            existing
+                .map(|f| f.trim_end_matches('\0').to_string())
                 .unwrap_or_else(|| rand_filename(rand, ctx))
         } else {
             // Generate a new one
@@ -284,7 +305,7 @@ impl GenerateArg for FilenameBuffer {
                 let len = if n_out_of(rand, 1, 3) {
                     rand.below(nonzero!(100)) as u64
                 } else {
-                    rand_filename_length(rand)
+                    rand_filename_length(rand, ctx.file_tree())
                 };
                 DataArg::Out(len)
             }
@@ -337,28 +358,74 @@ impl GenerateArg for ByteBuffer {
     }
 }
 
+impl StructType {
+    /// Make conditional fields present or absent according to their conditions,
+    /// where newly present fields are filled by `fill`.
+    pub(super) fn update_conditions<F>(&self, args: &mut [Arg], mut fill: F) -> Vec<Call>
+    where
+        F: FnMut(&Type) -> (Arg, Vec<Call>),
+    {
+        let mut calls = vec![];
+        for (i, field) in self.fields.iter().enumerate() {
+            let Some(condition) = &field.condition else {
+                continue;
+            };
+            let lookup = |name: &str| self.field_value(args, name);
+            let present = condition.evaluate(&lookup) != 0;
+
+            let union = args[i].enum_downcast_mut::<UnionArg>().unwrap();
+            match (present, union.index() == CONDITION_PRESENT) {
+                (true, false) => {
+                    let (arg, new_calls) = fill(&field.ty);
+                    *union = UnionArg::new(CONDITION_PRESENT, arg);
+                    calls.extend(new_calls);
+                }
+                (false, true) => {
+                    *union = UnionArg::new(CONDITION_ABSENT, GroupArg::new(vec![]).into());
+                }
+                _ => {}
+            }
+        }
+        calls
+    }
+
+    /// Integer value of a field, used by conditions.
+    fn field_value(&self, args: &[Arg], name: &str) -> Option<u64> {
+        let idx = self.fields.iter().position(|f| f.name == name)?;
+        match self.fields[idx].unwrap_arg(&args[idx])? {
+            Arg::ConstArg(arg) => Some(arg.0),
+            Arg::ResultArg(ResultArg::Literal(val)) => Some(*val),
+            _ => None,
+        }
+    }
+}
+
 impl GenerateArg for StructType {
     fn generate<R: Rand>(&self, rand: &mut R, ctx: &mut Context) -> (Arg, Vec<Call>) {
-        let (args, calls) = generate_args(rand, ctx, &self.fields);
+        let (mut args, mut calls) = generate_args(rand, ctx, &self.fields);
+        calls.extend(self.update_conditions(&mut args, |ty| generate_arg(rand, ctx, ty)));
+        update_computed_fields(&self.fields, &mut args, Some(self), None);
         let arg = GroupArg::new(args).into();
         (arg, calls)
     }
 
     fn default(&self) -> Arg {
-        let args: Vec<Arg> = self.fields.iter().map(|f| f.default()).collect();
+        let mut args: Vec<Arg> = self.fields.iter().map(|f| f.default()).collect();
+        self.update_conditions(&mut args, |ty| (ty.default(), vec![]));
+        update_computed_fields(&self.fields, &mut args, Some(self), None);
         GroupArg::new(args).into()
     }
 }
 
 impl GenerateArg for UnionType {
     fn generate<R: Rand>(&self, rand: &mut R, ctx: &mut Context) -> (Arg, Vec<Call>) {
-        let field = &self.fields[rand.below(self.fields.len().try_into().unwrap())];
-        generate_arg(rand, ctx, &field.ty)
+        let idx = rand.below(self.fields.len().try_into().unwrap());
+        let (arg, calls) = generate_arg(rand, ctx, &self.fields[idx].ty);
+        (UnionArg::new(idx, arg).into(), calls)
     }
 
     fn default(&self) -> Arg {
-        let field = &self.fields[0];
-        field.default()
+        UnionArg::new(0, self.fields[0].ty.default()).into()
     }
 }
 
@@ -472,6 +539,114 @@ impl GenerateArg for ResourceType {
     }
 }
 
+impl VmaType {
+    pub(super) fn generate_impl<R: Rand>(&self, rand: &mut R) -> u64 {
+        let pages = if let Some((min, max)) = self.pages {
+            rand.between(min as usize, max as usize) as u64
+        } else {
+            rand_page_count(rand)
+        };
+        let pages = pages.clamp(1, VMA_PAGES);
+        // Choose the first page so that the whole range stays in the mapped region
+        let start = rand.below(NonZeroUsize::new((VMA_PAGES - pages + 1) as usize).unwrap()) as u64;
+        VMA_BASE + start * PAGE_SIZE
+    }
+}
+
+impl GenerateArg for VmaType {
+    fn generate<R: Rand>(&self, rand: &mut R, _ctx: &mut Context) -> (Arg, Vec<Call>) {
+        // VMAs are plain addresses in harness rather than pointers to data
+        let addr = self.generate_impl(rand);
+        (ConstArg::new(addr).into(), vec![])
+    }
+
+    fn default(&self) -> Arg {
+        ConstArg::default().into()
+    }
+}
+
+impl GenerateArg for ConstType {
+    fn generate<R: Rand>(&self, _rand: &mut R, _ctx: &mut Context) -> (Arg, Vec<Call>) {
+        (self.default(), vec![])
+    }
+
+    fn default(&self) -> Arg {
+        ConstArg::new(truncate_to_bits(self.value, self.bits)).into()
+    }
+}
+
+impl ProcType {
+    pub(super) fn generate_impl<R: Rand>(&self, rand: &mut R) -> u64 {
+        // Each fuzzer client runs its own guest with a single harness process,
+        // so the process index is always 0.
+        let val =
+            self.start + rand.below(NonZeroUsize::new(self.per_proc as usize).unwrap()) as u64;
+        truncate_to_bits(val, self.bits)
+    }
+}
+
+impl GenerateArg for ProcType {
+    fn generate<R: Rand>(&self, rand: &mut R, _ctx: &mut Context) -> (Arg, Vec<Call>) {
+        (ConstArg::new(self.generate_impl(rand)).into(), vec![])
+    }
+
+    fn default(&self) -> Arg {
+        ConstArg::new(truncate_to_bits(self.start, self.bits)).into()
+    }
+}
+
+impl CsumType {
+    /// Compute the checksum of the given data.
+    /// Ref: RFC 1071, syzkaller/prog/checksum.go
+    pub(super) fn compute(&self, data: &[u8]) -> u64 {
+        let mut bytes = Vec::new();
+        if let CsumKind::Pseudo(proto) = self.kind {
+            // TODO: Addresses of the enclosing IP header are unknown here,
+            // so only the protocol and the length are included
+            bytes.extend([0, proto as u8]);
+            bytes.extend((data.len() as u16).to_be_bytes());
+        }
+        bytes.extend(data);
+
+        // The sum is independent of the byte order as long as the words are
+        // read and the result is written in the same order.
+        let mut sum: u64 = 0;
+        for chunk in bytes.chunks(2) {
+            let word = [chunk[0], chunk.get(1).copied().unwrap_or(0)];
+            sum += match ENDIAN {
+                Endian::Little => u16::from_le_bytes(word),
+                Endian::Big => u16::from_be_bytes(word),
+            } as u64;
+        }
+        while sum >> 16 != 0 {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        truncate_to_bits(!sum & 0xffff, self.bits)
+    }
+}
+
+impl GenerateArg for LenType {
+    fn generate<R: Rand>(&self, _rand: &mut R, _ctx: &mut Context) -> (Arg, Vec<Call>) {
+        // Computed by the enclosing struct or syscall after all fields are generated
+        (self.default(), vec![])
+    }
+
+    fn default(&self) -> Arg {
+        ConstArg::default().into()
+    }
+}
+
+impl GenerateArg for CsumType {
+    fn generate<R: Rand>(&self, _rand: &mut R, _ctx: &mut Context) -> (Arg, Vec<Call>) {
+        // Computed by the enclosing struct after all fields are generated
+        (self.default(), vec![])
+    }
+
+    fn default(&self) -> Arg {
+        ConstArg::default().into()
+    }
+}
+
 // Helper functions
 
 /// Returns a random int in range [0..n),
@@ -485,7 +660,7 @@ fn biased_rand<R: Rand>(rand: &mut R, n: usize, k: usize) -> u64 {
 }
 
 /// Generate a random integer.
-fn rand_int<R: Rand>(rand: &mut R, bits: u8) -> u64 {
+pub(super) fn rand_int<R: Rand>(rand: &mut R, bits: u8) -> u64 {
     let mut val = rand.next();
 
     // Set the value into a range
@@ -512,7 +687,52 @@ fn rand_int<R: Rand>(rand: &mut R, bits: u8) -> u64 {
     }
 
     // Truncate value to the number of bits
-    val & ((1 << bits) - 1)
+    truncate_to_bits(val, bits)
+}
+
+/// Generate a random number of pages.
+// Ref: syzkaller/prog/rand.go
+fn rand_page_count<R: Rand>(rand: &mut R) -> u64 {
+    if n_out_of(rand, 100, 106) {
+        rand.below(nonzero!(4)) as u64 + 1
+    } else if n_out_of(rand, 5, 6) {
+        rand.below(nonzero!(20)) as u64 + 1
+    } else {
+        rand.below(NonZeroUsize::new(VMA_PAGES as usize).unwrap()) as u64 + 1
+    }
+}
+
+/// Generate a special pointer value, which is likely to be invalid for the kernel.
+fn rand_special_pointer<R: Rand>(rand: &mut R) -> u64 {
+    let vma_end = VMA_BASE + VMA_PAGES * PAGE_SIZE;
+    match rand.below(nonzero!(6)) {
+        // Null pointer
+        0 => 0,
+        // Kernel space
+        1 => {
+            let base = KERNEL_ADDRESSES[rand.below(KERNEL_ADDRESSES.len().try_into().unwrap())];
+            base + rand.below(nonzero!(0x10)) as u64 * PAGE_SIZE
+        }
+        // Unaligned address in the mapped region
+        2 => {
+            VMA_BASE
+                + rand.below(nonzero!(8)) as u64 * PAGE_SIZE
+                + 1
+                + rand.below(nonzero!(7)) as u64
+        }
+        // Unmapped user space, just past the mapped region or in the lowest pages
+        3 => {
+            if binary(rand) {
+                vma_end + rand.below(nonzero!(4)) as u64 * PAGE_SIZE
+            } else {
+                rand.below(nonzero!(0x10)) as u64 * PAGE_SIZE
+            }
+        }
+        // End of the mapped region, so that accesses cross into unmapped memory
+        4 => vma_end - 1 - rand.below(nonzero!(0x10)) as u64,
+        // All bits set, also non-canonical on 64-bit targets
+        _ => u64::MAX,
+    }
 }
 
 /// Generate a random array length.
@@ -541,16 +761,19 @@ fn rand_string<R: Rand>(rand: &mut R) -> String {
 
 /// Generate a random filename.
 fn rand_filename<R: Rand>(rand: &mut R, ctx: &Context) -> String {
-    let mut dir = if binary(rand) {
-        sample_from_iter(rand, ctx.filenames().iter())
-            .cloned()
-            .unwrap_or_else(|| ".".to_string())
-    } else {
-        ".".to_string()
+    let tree = ctx.file_tree();
+    let dir = match rand.below(nonzero!(4)) {
+        // Under a generated file, which may be a directory
+        0 => sample_from_iter(rand, ctx.filenames().iter()),
+        // Under a pre-created directory
+        1 => sample_from_iter(rand, tree.dirs().iter()),
+        // Through a symbolic link
+        2 => sample_from_iter(rand, tree.symlinks().iter().map(|(link, _)| link)),
+        _ => None,
     };
-    if dir.ends_with("\0") {
-        dir.pop();
-    }
+    let mut dir = dir
+        .map_or(".", |d| d.trim_end_matches(['\0', '/']))
+        .to_string();
     if one_of(rand, 10)
         && path_clean::clean(&dir)
             .into_os_string()
@@ -564,27 +787,25 @@ fn rand_filename<R: Rand>(rand: &mut R, ctx: &Context) -> String {
     for i in 0usize.. {
         let mut name = format!("{}/file{}", dir, i);
         if one_of(rand, 100) {
-            // Vary the length
-            let len = rand_filename_length(rand) as usize;
+            // Vary the length around the limits of the target
+            let len = rand_filename_length(rand, tree) as usize;
             if len > name.len() {
                 name += str::repeat("a", len - name.len()).as_str();
             }
         }
-        if !ctx.filenames().contains(&name) {
+        if !ctx.filenames().contains(&name) && !tree.paths().any(|p| *p == name) {
             return name;
         }
     }
-
     unreachable!()
 }
 
-/// Generate a random filename length.
-pub(super) fn rand_filename_length<R: Rand>(rand: &mut R) -> u64 {
-    // TODO: Configure for different targets
-    const SPECIAL_FILE_LENGTHS: [u64; 1] = [4096];
+/// Generate a random filename length around the limits of the target.
+pub(super) fn rand_filename_length<R: Rand>(rand: &mut R, tree: &FileTree) -> u64 {
+    let special_lengths = [tree.path_max(), tree.name_max()];
 
     let off = biased_rand(rand, 10, 5);
-    let len = SPECIAL_FILE_LENGTHS[rand.below(nonzero!(SPECIAL_FILE_LENGTHS.len()))];
+    let len = special_lengths[rand.below(nonzero!(2))];
     let res = if binary(rand) {
         len + off
     } else {
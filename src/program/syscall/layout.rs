@@ -0,0 +1,326 @@
+//! C-compatible memory layout of arguments on the target.
+//!
+//! Harness materializes arguments into memory by itself, so the layout here is
+//! used for the values depending on memory representations, e.g. `bytesize`
+//! fields and checksums. It follows the rules of the C ABI of the target:
+//! fields are aligned to their natural alignment unless the struct is `[packed]`,
+//! consecutive bitfields of the same size share a storage unit, and structs are
+//! padded at the end to their alignment or to `[size[N]]`.
+
+use enum_downcast::EnumDowncast;
+use log::debug;
+
+use super::generation::GenerateArg;
+use super::{Field, LenKind, StructType, Type, UnionType};
+use crate::program::{
+    call::{Arg, ConstArg, DataArg, GroupArg, PointerArg, ResultArg, UnionArg},
+    metadata::{truncate_to_bits, ENDIAN, PTR_SIZE},
+};
+
+impl Type {
+    /// Alignment of this type on the target, in bytes.
+    pub fn align(&self) -> u64 {
+        match self {
+            Type::Int(inner) => inner.bits as u64 / 8,
+            Type::Flag(inner) => inner.bits as u64 / 8,
+            Type::Const(inner) => inner.bits as u64 / 8,
+            Type::Proc(inner) => inner.bits as u64 / 8,
+            Type::Csum(inner) => inner.bits as u64 / 8,
+            Type::Len(inner) => inner.bits as u64 / 8,
+            Type::Resource(inner) => inner.bits as u64 / 8,
+            Type::Vma(_) | Type::Pointer(_) => PTR_SIZE as u64,
+            Type::Buffer(_) => 1,
+            Type::Array(inner) => inner.elem.align(),
+            Type::Struct(inner) => inner.align_impl(),
+            Type::Union(inner) => inner.align_impl(),
+        }
+    }
+
+    /// Size in bits of the storage unit and the length of the bitfield, if this is
+    /// a bitfield, e.g. `(32, 4)` for `int32:4` and `flags[f, int32:4]`.
+    pub fn bitfield(&self) -> Option<(u8, u8)> {
+        let (bits, len) = match self {
+            Type::Int(inner) => (inner.bits, inner.bitfield_len),
+            Type::Flag(inner) => (inner.bits, inner.bitfield_len),
+            Type::Const(inner) => (inner.bits, inner.bitfield_len),
+            Type::Proc(inner) => (inner.bits, inner.bitfield_len),
+            Type::Len(inner) => (inner.bits, inner.bitfield_len),
+            _ => return None,
+        };
+        len.map(|len| (bits, len))
+    }
+
+    /// Size of an argument of this type on the target, in bytes.
+    pub fn byte_size(&self, arg: &Arg) -> u64 {
+        self.to_raw_bytes(arg).len() as u64
+    }
+
+    /// Memory representation of an argument of this type on the target.
+    ///
+    /// Pointers are represented by their special address, or zero if the pointee
+    /// is allocated by harness, since the address is unknown to the fuzzer.
+    /// Output buffers are filled with zeros.
+    pub fn to_raw_bytes(&self, arg: &Arg) -> Vec<u8> {
+        match self {
+            Type::Int(inner) => int_bytes(arg, inner.bits),
+            Type::Flag(inner) => int_bytes(arg, inner.bits),
+            Type::Const(inner) => int_bytes(arg, inner.bits),
+            Type::Proc(inner) => int_bytes(arg, inner.bits),
+            Type::Csum(inner) => int_bytes(arg, inner.bits),
+            Type::Len(inner) => int_bytes(arg, inner.bits),
+            Type::Vma(_) => int_bytes(arg, (PTR_SIZE * 8) as u8),
+            Type::Resource(inner) => {
+                let val = match arg.enum_downcast_ref::<ResultArg>().unwrap() {
+                    ResultArg::Literal(val) => *val,
+                    ResultArg::Ref(_) => 0,
+                };
+                ENDIAN.encode(val, inner.bits as usize / 8)
+            }
+            Type::Pointer(_) => {
+                let addr = match arg.enum_downcast_ref::<PointerArg>().unwrap() {
+                    PointerArg::Addr(addr) => *addr,
+                    PointerArg::Data(_) => 0,
+                };
+                ENDIAN.encode(addr, PTR_SIZE)
+            }
+            Type::Buffer(_) => match arg.enum_downcast_ref::<DataArg>().unwrap() {
+                DataArg::In(data) => data.clone(),
+                DataArg::Out(len) => vec![0; *len as usize],
+            },
+            Type::Array(inner) => {
+                let group = arg.enum_downcast_ref::<GroupArg>().unwrap();
+                group
+                    .args()
+                    .iter()
+                    .flat_map(|arg| inner.elem.to_raw_bytes(arg))
+                    .collect()
+            }
+            Type::Struct(inner) => {
+                let group = arg.enum_downcast_ref::<GroupArg>().unwrap();
+                inner.to_raw_bytes_impl(group.args())
+            }
+            Type::Union(inner) => union_bytes(inner, arg),
+        }
+    }
+}
+
+impl StructType {
+    fn align_impl(&self) -> u64 {
+        if let Some(align) = self.align {
+            align
+        } else if self.packed {
+            1
+        } else {
+            self.fields.iter().map(|f| f.ty.align()).max().unwrap_or(1)
+        }
+    }
+
+    fn to_raw_bytes_impl(&self, args: &[Arg]) -> Vec<u8> {
+        self.layout_impl(args).0
+    }
+
+    /// Memory representation of the struct, and the offsets of the fields, which are
+    /// `None` for absent fields, bitfields and fields truncated by `[size[N]]`.
+    pub(super) fn layout_impl(&self, args: &[Arg]) -> (Vec<u8>, Vec<Option<usize>>) {
+        let mut bytes = Vec::new();
+        let mut offsets = vec![None; self.fields.len()];
+        // End offsets of the fields, to drop the ones truncated by `[size[N]]`
+        let mut ends = vec![0; self.fields.len()];
+        // Offset, size and used bits of the current bitfield storage unit
+        let mut unit: Option<(usize, u8, u8)> = None;
+
+        for (i, (field, arg)) in self.fields.iter().zip(args).enumerate() {
+            // Absent conditional fields take no space
+            let Some(arg) = field.unwrap_arg(arg) else {
+                continue;
+            };
+
+            if let Some((unit_bits, len)) = field.ty.bitfield() {
+                let val = arg.enum_downcast_ref::<ConstArg>().unwrap().0;
+                let (offset, used) = match unit {
+                    // Fits in the current storage unit
+                    Some((offset, bits, used)) if bits == unit_bits && used + len <= bits => {
+                        (offset, used)
+                    }
+                    // Start a new storage unit
+                    _ => {
+                        if !self.packed {
+                            pad_to(&mut bytes, field.ty.align());
+                        }
+                        let offset = bytes.len();
+                        bytes.resize(offset + unit_bits as usize / 8, 0);
+                        (offset, 0)
+                    }
+                };
+                let storage = &mut bytes[offset..offset + unit_bits as usize / 8];
+                let mut unit_val = ENDIAN.decode(storage);
+                unit_val |= truncate_to_bits(val, len) << used;
+                storage.copy_from_slice(&ENDIAN.encode(unit_val, storage.len()));
+                unit = Some((offset, unit_bits, used + len));
+                continue;
+            }
+
+            unit = None;
+            if !self.packed {
+                pad_to(&mut bytes, field.ty.align());
+            }
+            offsets[i] = Some(bytes.len());
+            bytes.extend(field.ty.to_raw_bytes(arg));
+            ends[i] = bytes.len();
+        }
+
+        if let Some(size) = self.size {
+            // Varlen fields may grow beyond `[size[N]]`, which are truncated to it as
+            // the target only sees the first N bytes
+            if bytes.len() as u64 > size {
+                debug!(
+                    "[StructType] Truncate struct of {} bytes to {size}",
+                    bytes.len()
+                );
+                for (offset, end) in offsets.iter_mut().zip(&ends) {
+                    if *end > size as usize {
+                        *offset = None;
+                    }
+                }
+            }
+            bytes.resize(size as usize, 0);
+        } else {
+            pad_to(&mut bytes, self.align_impl());
+        }
+        (bytes, offsets)
+    }
+}
+
+impl UnionType {
+    fn align_impl(&self) -> u64 {
+        self.fields.iter().map(|f| f.ty.align()).max().unwrap_or(1)
+    }
+}
+
+/// Compute the values of fields depending on their siblings, i.e. lengths and checksums.
+/// `parent` is the struct containing the fields, or `None` for syscall arguments.
+/// The field at `preserve`, if any, is left untouched, e.g. when it has just been mutated.
+pub fn update_computed_fields(
+    fields: &[Field],
+    args: &mut [Arg],
+    parent: Option<&StructType>,
+    preserve: Option<usize>,
+) {
+    // Lengths first, since checksums may cover them
+    for (i, field) in fields.iter().enumerate() {
+        let Type::Len(len) = &field.ty else {
+            continue;
+        };
+        if preserve == Some(i) {
+            continue;
+        }
+        let val = if len.target == "parent" {
+            parent.map_or(0, |st| {
+                let size = st.to_raw_bytes_impl(args).len() as u64;
+                if len.kind == LenKind::Bitsize {
+                    size * 8
+                } else {
+                    size
+                }
+            })
+        } else {
+            let idx = find_field(fields, &len.target);
+            fields[idx]
+                .unwrap_arg(&args[idx])
+                .map_or(0, |arg| length_of(&fields[idx].ty, arg, len.kind))
+        };
+        set_int(field, &mut args[i], truncate_to_bits(val, len.bits));
+    }
+
+    for (i, field) in fields.iter().enumerate() {
+        let Type::Csum(csum) = &field.ty else {
+            continue;
+        };
+        if preserve == Some(i) {
+            continue;
+        }
+
+        // The checksum field itself is considered to be zero during computation
+        set_int(field, &mut args[i], 0);
+        let data = if csum.target == "parent" {
+            let Some(st) = parent else {
+                continue;
+            };
+            st.to_raw_bytes_impl(args)
+        } else {
+            let idx = find_field(fields, &csum.target);
+            fields[idx]
+                .unwrap_arg(&args[idx])
+                .map(|arg| fields[idx].ty.to_raw_bytes(arg))
+                .unwrap_or_default()
+        };
+        let val = csum.compute(&data);
+        set_int(field, &mut args[i], val);
+    }
+}
+
+/// Set the value of an integer field if it is present.
+fn set_int(field: &Field, arg: &mut Arg, val: u64) {
+    if let Some(arg) = field.unwrap_arg_mut(arg) {
+        arg.enum_downcast_mut::<ConstArg>().unwrap().0 = val;
+    }
+}
+
+/// Length of an argument of the given type.
+fn length_of(ty: &Type, arg: &Arg, kind: LenKind) -> u64 {
+    match ty {
+        // Length of the pointee
+        Type::Pointer(inner) => match arg.enum_downcast_ref::<PointerArg>().unwrap() {
+            PointerArg::Data(data) => length_of(&inner.elem, data, kind),
+            PointerArg::Addr(_) => 0,
+        },
+        Type::Array(_) if kind == LenKind::Len => {
+            arg.enum_downcast_ref::<GroupArg>().unwrap().args().len() as u64
+        }
+        _ => {
+            let size = ty.byte_size(arg);
+            match kind {
+                LenKind::Len | LenKind::Bytesize => size,
+                LenKind::Bitsize => size * 8,
+            }
+        }
+    }
+}
+
+fn find_field(fields: &[Field], name: &str) -> usize {
+    fields
+        .iter()
+        .position(|f| f.name == name)
+        .unwrap_or_else(|| panic!("Unknown target field: {name}"))
+}
+
+fn int_bytes(arg: &Arg, bits: u8) -> Vec<u8> {
+    let arg = arg.enum_downcast_ref::<ConstArg>().unwrap();
+    ENDIAN.encode(arg.0, bits as usize / 8)
+}
+
+fn union_bytes(union: &UnionType, arg: &Arg) -> Vec<u8> {
+    let arg = arg.enum_downcast_ref::<UnionArg>().unwrap();
+    let mut bytes = union.fields[arg.index()].ty.to_raw_bytes(arg.arg());
+    if !union.varlen {
+        // Size of the largest variant, taking default values for varlen variants
+        let size = union
+            .fields
+            .iter()
+            .map(|f| f.ty.byte_size(&f.ty.default()))
+            .max()
+            .unwrap_or(0);
+        if bytes.len() < size as usize {
+            bytes.resize(size as usize, 0);
+        }
+        pad_to(&mut bytes, union.align_impl());
+    }
+    bytes
+}
+
+/// Pad the bytes with zeros to the given alignment.
+fn pad_to(bytes: &mut Vec<u8>, align: u64) {
+    let len = bytes.len() as u64;
+    let padded = len.next_multiple_of(align.max(1));
+    bytes.resize(padded as usize, 0);
+}
@@ -0,0 +1,144 @@
+//! Memory of a standalone program, where the pointees of arguments are laid out.
+//!
+//! Unlike the harness, which materializes arguments by itself, a standalone program
+//! carries all the pointees in a single buffer. Pointers in the buffer are patched to
+//! the addresses of their pointees, and resources to the results of previous calls,
+//! at runtime.
+
+use enum_downcast::EnumDowncast;
+use uuid::Uuid;
+
+use super::{Syscall, Type};
+use crate::program::call::{Arg, Call, GroupArg, PointerArg, ResultArg, UnionArg};
+
+/// Value of a syscall argument passed in a register.
+#[derive(Debug, Clone, Copy)]
+pub enum RegValue {
+    Const(u64),
+    /// Address of the pointee at the offset in the memory
+    Pointer(usize),
+    /// Result of a previous call
+    Result(Uuid),
+}
+
+/// A pointer or resource in the memory, to be patched at runtime.
+#[derive(Debug, Clone, Copy)]
+pub enum Fixup {
+    /// Address of the pointee at the offset in the memory
+    Pointer(usize),
+    /// Result of a previous call, truncated to the size in bytes
+    Result(Uuid, usize),
+}
+
+/// Pointees of all the arguments, and the fixups by their offsets in the memory.
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    bytes: Vec<u8>,
+    fixups: Vec<(usize, Fixup)>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn fixups(&self) -> &[(usize, Fixup)] {
+        &self.fixups
+    }
+
+    /// Lay out the pointees of the arguments of the call, returning the values of
+    /// the registers, or an error if an argument does not fit in a register.
+    pub fn place_call(&mut self, syscall: &Syscall, call: &Call) -> Result<Vec<RegValue>, String> {
+        syscall
+            .fields()
+            .iter()
+            .zip(call.args())
+            .map(|(field, arg)| match arg {
+                Arg::ConstArg(value) => Ok(RegValue::Const(value.0)),
+                Arg::PointerArg(PointerArg::Addr(addr)) => Ok(RegValue::Const(*addr)),
+                Arg::PointerArg(PointerArg::Data(data)) => {
+                    let Type::Pointer(ptr) = &field.ty else {
+                        unreachable!("Pointer argument of non-pointer type {:?}", field.ty);
+                    };
+                    Ok(RegValue::Pointer(self.place(&ptr.elem, data)))
+                }
+                Arg::ResultArg(ResultArg::Ref(id)) => Ok(RegValue::Result(*id)),
+                Arg::ResultArg(ResultArg::Literal(literal)) => Ok(RegValue::Const(*literal)),
+                // Syzlang passes buffers, arrays, structs and unions by pointers, but
+                // descriptions are not checked against it
+                Arg::DataArg(_) | Arg::GroupArg(_) | Arg::UnionArg(_) => Err(format!(
+                    "Argument {} of {} is not passed in a register: {arg:?}",
+                    field.name,
+                    syscall.name()
+                )),
+            })
+            .collect()
+    }
+
+    /// Place the argument at the end of the memory, aligned, returning its offset.
+    fn place(&mut self, ty: &Type, arg: &Arg) -> usize {
+        // Pointees of the argument are placed before it
+        let fixups = self.fixups_of(ty, arg);
+        let bytes = ty.to_raw_bytes(arg);
+        let offset = self
+            .bytes
+            .len()
+            .next_multiple_of(ty.align().max(1) as usize);
+        self.bytes.resize(offset, 0);
+        self.bytes.extend(bytes);
+        self.fixups.extend(shift(fixups, offset));
+        offset
+    }
+
+    /// Fixups in the memory representation of the argument by their relative offsets,
+    /// placing the pointees in the memory.
+    fn fixups_of(&mut self, ty: &Type, arg: &Arg) -> Vec<(usize, Fixup)> {
+        match ty {
+            Type::Pointer(inner) => match arg.enum_downcast_ref::<PointerArg>().unwrap() {
+                PointerArg::Data(data) => vec![(0, Fixup::Pointer(self.place(&inner.elem, data)))],
+                PointerArg::Addr(_) => Vec::new(),
+            },
+            Type::Resource(inner) => match arg.enum_downcast_ref::<ResultArg>().unwrap() {
+                ResultArg::Ref(id) => vec![(0, Fixup::Result(*id, inner.bits as usize / 8))],
+                ResultArg::Literal(_) => Vec::new(),
+            },
+            Type::Array(inner) => {
+                let group = arg.enum_downcast_ref::<GroupArg>().unwrap();
+                let mut offset = 0;
+                let mut fixups = Vec::new();
+                for arg in group.args() {
+                    fixups.extend(shift(self.fixups_of(&inner.elem, arg), offset));
+                    offset += inner.elem.byte_size(arg) as usize;
+                }
+                fixups
+            }
+            Type::Struct(inner) => {
+                let args = arg.enum_downcast_ref::<GroupArg>().unwrap().args();
+                let (_, offsets) = inner.layout_impl(args);
+                let mut fixups = Vec::new();
+                for ((field, arg), offset) in inner.fields.iter().zip(args).zip(offsets) {
+                    if let (Some(arg), Some(offset)) = (field.unwrap_arg(arg), offset) {
+                        fixups.extend(shift(self.fixups_of(&field.ty, arg), offset));
+                    }
+                }
+                fixups
+            }
+            Type::Union(inner) => {
+                let arg = arg.enum_downcast_ref::<UnionArg>().unwrap();
+                self.fixups_of(&inner.fields[arg.index()].ty, arg.arg())
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn shift(fixups: Vec<(usize, Fixup)>, offset: usize) -> Vec<(usize, Fixup)> {
+    fixups
+        .into_iter()
+        .map(|(relative, fixup)| (offset + relative, fixup))
+        .collect()
+}
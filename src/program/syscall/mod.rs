@@ -1,4 +1,8 @@
+mod bytes;
+mod condition;
 mod generation;
+mod layout;
+mod memory;
 mod mutation;
 
 use std::iter;
@@ -7,16 +11,20 @@ use libafl_bolts::rands::Rand;
 
 use enum_common_fields::EnumCommonFields;
 use enum_dispatch::enum_dispatch;
+use enum_downcast::EnumDowncast;
 use syzlang_parser::parser::{
     ArgOpt, ArgType, Argument, Direction as ParserDirection, Flag, Function, IdentType, Identifier,
     Parsed, Resource, Struct, Union, Value,
 };
 
-use super::call::{Arg, Call};
+use super::call::{Arg, Call, UnionArg};
 use super::context::Context;
-use super::metadata::ARCH;
+use super::metadata::{ARCH, PTR_SIZE};
 
+pub use condition::Condition;
 pub use generation::GenerateArg;
+pub use layout::update_computed_fields;
+pub use memory::{Fixup, Memory, RegValue};
 pub use mutation::MutateArg;
 
 #[derive(Debug, Clone)]
@@ -46,6 +54,11 @@ impl Syscall {
         self.nr
     }
 
+    /// Name in the description, including the variant, e.g. `openat$dir`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn fields(&self) -> &[Field] {
         &self.fields
     }
@@ -60,6 +73,8 @@ pub struct Field {
     pub name: String,
     pub ty: Type,
     pub dir: Direction,
+    /// Condition for the field to be present, only for struct fields
+    pub condition: Option<Condition>,
 }
 
 impl Field {
@@ -68,10 +83,44 @@ impl Field {
             name: argument.name.name.clone(),
             ty: Type::from_argument(argument, ctx).unwrap(),
             dir: argument.direction().into(),
+            condition: find_condition(&argument.opts).map(|expr| Condition::parse(expr, ctx)),
+        }
+    }
+
+    /// Wrap the argument of a conditional field as present.
+    pub fn present(&self, arg: Arg) -> Arg {
+        if self.condition.is_some() {
+            UnionArg::new(CONDITION_PRESENT, arg).into()
+        } else {
+            arg
         }
     }
+
+    /// Get the underlying argument, or `None` if the conditional field is absent.
+    pub fn unwrap_arg<'a>(&self, arg: &'a Arg) -> Option<&'a Arg> {
+        if self.condition.is_none() {
+            return Some(arg);
+        }
+        let union = arg.enum_downcast_ref::<UnionArg>().unwrap();
+        (union.index() == CONDITION_PRESENT).then(|| union.arg())
+    }
+
+    /// Mutable version of [`Field::unwrap_arg`].
+    pub fn unwrap_arg_mut<'a>(&self, arg: &'a mut Arg) -> Option<&'a mut Arg> {
+        if self.condition.is_none() {
+            return Some(arg);
+        }
+        let union = arg.enum_downcast_mut::<UnionArg>().unwrap();
+        (union.index() == CONDITION_PRESENT).then(|| union.arg_mut())
+    }
 }
 
+/// Variant index of a present conditional field.
+pub const CONDITION_PRESENT: usize = 0;
+
+/// Variant index of an absent conditional field.
+pub const CONDITION_ABSENT: usize = 1;
+
 #[enum_dispatch(GenerateArg, MutateArg)]
 #[derive(Debug, Clone)]
 pub enum Type {
@@ -83,6 +132,11 @@ pub enum Type {
     Struct(StructType),
     Union(UnionType),
     Resource(ResourceType),
+    Vma(VmaType),
+    Const(ConstType),
+    Proc(ProcType),
+    Csum(CsumType),
+    Len(LenType),
 }
 
 impl Type {
@@ -104,7 +158,7 @@ impl Type {
                 };
                 Some(ty)
             }
-            ArgType::String | ArgType::StringNoz => {
+            ArgType::String | ArgType::StringNoz | ArgType::Glob => {
                 Some(BufferType::from_argument(argument, ctx).into())
             }
             ArgType::Ident(ident) => {
@@ -127,13 +181,28 @@ impl Type {
                 };
                 Some(ty)
             }
+            ArgType::Vma | ArgType::Vma64 => Some(VmaType::from_argument(argument).into()),
+            ArgType::Const => Some(ConstType::from_argument(argument, ctx).into()),
+            ArgType::Proc => Some(ProcType::from_argument(argument).into()),
+            ArgType::Csum => Some(CsumType::from_argument(argument, ctx).into()),
+            ArgType::Len | ArgType::Bytesize | ArgType::Bitsize => {
+                Some(LenType::from_argument(argument).into())
+            }
             ArgType::Void => None,
             _ => unimplemented!("Unsupported argument type: {:?}", argument.argtype),
         }
     }
 
     pub fn is_integer(&self) -> bool {
-        matches!(self, Self::Int(_) | Self::Flag(_))
+        matches!(
+            self,
+            Self::Int(_)
+                | Self::Flag(_)
+                | Self::Const(_)
+                | Self::Proc(_)
+                | Self::Csum(_)
+                | Self::Len(_)
+        )
     }
 
     pub fn is_string(&self) -> bool {
@@ -162,6 +231,11 @@ impl Type {
             Self::Struct(inner) => &inner.attr,
             Self::Union(inner) => &inner.attr,
             Self::Resource(inner) => &inner.attr,
+            Self::Vma(inner) => &inner.attr,
+            Self::Const(inner) => &inner.attr,
+            Self::Proc(inner) => &inner.attr,
+            Self::Csum(inner) => &inner.attr,
+            Self::Len(inner) => &inner.attr,
         }
     }
 }
@@ -202,33 +276,43 @@ pub struct IntType {
     attr: TypeAttr,
     bits: u8,
     range: Option<(u64, u64)>,
+    /// Number of bits for bitfields, e.g. 3 for `int32:3`
+    bitfield_len: Option<u8>,
 }
 
 impl IntType {
     fn from_argument(argument: &Argument) -> Self {
         let attr = TypeAttr::from_opts(&argument.opts);
-        let arg_type = argument.arg_type();
-        let bits = match argument.argtype {
-            ArgType::Int8 | ArgType::Int16 | ArgType::Int32 | ArgType::Int64 | ArgType::Intptr => {
-                (arg_type.evaluate_size(&ARCH).unwrap() * 8) as u8
-            }
-            _ => unreachable!("Invalid argument type for integer"),
-        };
-        assert!(bits > 0, "Integer size has to be positive");
+        let bits = int_bits(argument);
         let range = find_range(&argument.opts);
-        Self { attr, bits, range }
+        let bitfield_len = find_bitfield_len(&argument.opts, bits);
+        Self {
+            attr,
+            bits,
+            range,
+            bitfield_len,
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct FlagType {
     attr: TypeAttr,
+    bits: u8,
+    /// Number of bits for bitfields, e.g. 4 for `flags[f, int32:4]`
+    bitfield_len: Option<u8>,
     values: Vec<u64>,
     is_bitmask: bool,
 }
 
 impl FlagType {
-    fn from_flag(flag: &Flag, ctx: &Parsed, attr: TypeAttr) -> Self {
+    fn from_flag(
+        flag: &Flag,
+        ctx: &Parsed,
+        attr: TypeAttr,
+        bits: u8,
+        bitfield_len: Option<u8>,
+    ) -> Self {
         let mut values: Vec<u64> = flag
             .args()
             .map(|arg| value_to_u64_flatten(arg, ctx).unwrap())
@@ -237,6 +321,8 @@ impl FlagType {
         let is_bitmask = is_bitmask(&values);
         Self {
             attr,
+            bits,
+            bitfield_len,
             values,
             is_bitmask,
         }
@@ -246,7 +332,9 @@ impl FlagType {
         let flag_name = find_ident(&argument.opts).expect("No flag name for flag type");
         let flag = ctx.get_flag(flag_name).unwrap();
         let attr = TypeAttr::from_opts(&argument.opts);
-        Self::from_flag(flag, ctx, attr)
+        let bits = subarg_bits(&argument.opts);
+        let bitfield_len = subarg_bitfield_len(&argument.opts, bits);
+        Self::from_flag(flag, ctx, attr, bits, bitfield_len)
     }
 }
 
@@ -310,6 +398,8 @@ impl BufferType {
             }
             // stringnoz
             ArgType::StringNoz => StringBuffer::from_argument(argument, ctx).into(),
+            // glob["pattern"]
+            ArgType::Glob => StringBuffer::from_glob(argument).into(),
             // array[int8], the underlying type should be ensured by the caller
             ArgType::Array => ByteBuffer::from_argument(argument).into(),
             _ => unreachable!("Invalid argument type for buffer kind"),
@@ -331,8 +421,10 @@ impl StringBuffer {
             // Use the const values if provided in the argument
             vec![value]
         } else {
-            // If no values are provided, try to get them from the flag
-            if let Some(flag_name) = find_ident(&argument.opts) {
+            // If no values are provided, try to get them from the string flag set,
+            // which is referenced as a value after post-processing, e.g. `string[filesystem]`
+            if let Some(flag_name) = find_ident(&argument.opts).or(find_ident_value(&argument.opts))
+            {
                 let flag = ctx
                     .get_flag(flag_name)
                     .expect(format!("Unknown string flag: {flag_name}").as_str());
@@ -351,6 +443,35 @@ impl StringBuffer {
             no_zero,
         }
     }
+
+    /// Globs are expanded on the target by syzkaller, which requires knowledge of
+    /// the guest file system. Here we take the directories before the first wildcard
+    /// of each pattern instead, e.g. `/sys/class` for `/sys/class/*/uevent`.
+    fn from_glob(argument: &Argument) -> Self {
+        let attr = TypeAttr::from_opts(&argument.opts);
+        let pattern = find_string_value(&argument.opts).expect("Missing glob pattern");
+        let mut values: Vec<String> = pattern
+            .split(':')
+            // Patterns starting with `-` are excluded
+            .filter(|p| !p.is_empty() && !p.starts_with('-'))
+            .map(|p| {
+                let end = p.find(['*', '?', '[']).unwrap_or(p.len());
+                let prefix = &p[..end];
+                match prefix.rfind('/') {
+                    Some(pos) if end < p.len() => prefix[..pos.max(1)].to_string(),
+                    _ => prefix.to_string(),
+                }
+            })
+            .collect();
+        values.sort();
+        values.dedup();
+
+        Self {
+            attr,
+            values,
+            no_zero: false,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -385,16 +506,39 @@ impl ByteBuffer {
 pub struct StructType {
     attr: TypeAttr,
     fields: Vec<Field>,
+    /// No padding between fields, `[packed]`
+    packed: bool,
+    /// Alignment of the struct, `[align[N]]`
+    align: Option<u64>,
+    /// Size of the struct, `[size[N]]`
+    size: Option<u64>,
 }
 
 impl StructType {
     fn from_struct(st: &Struct, ctx: &Parsed, attr: TypeAttr) -> Self {
+        let mut packed = false;
+        let mut align = None;
+        let mut size = None;
+        for opt in st.opts.iter() {
+            match opt {
+                ArgOpt::Packed => packed = true,
+                ArgOpt::Align(val) => align = Some(value_to_u64(val)),
+                ArgOpt::Size(val) => size = Some(value_to_u64(val)),
+                _ => {}
+            }
+        }
+        if let Some(align) = align {
+            assert!(align.is_power_of_two(), "Invalid struct alignment {align}");
+        }
         Self {
             attr,
             fields: st
                 .args()
                 .map(|arg| Field::from_argument(arg, ctx))
                 .collect(),
+            packed,
+            align,
+            size,
         }
     }
 }
@@ -403,16 +547,20 @@ impl StructType {
 pub struct UnionType {
     attr: TypeAttr,
     fields: Vec<Field>,
+    /// Size of the union is the size of the chosen variant, `[varlen]`
+    varlen: bool,
 }
 
 impl UnionType {
     fn from_union(union: &Union, ctx: &Parsed, attr: TypeAttr) -> Self {
+        let varlen = union.opts.iter().any(|opt| matches!(opt, ArgOpt::Varlen));
         Self {
             attr,
             fields: union
                 .args()
                 .map(|arg| Field::from_argument(arg, ctx))
                 .collect(),
+            varlen,
         }
     }
 }
@@ -421,6 +569,7 @@ impl UnionType {
 pub struct ResourceType {
     attr: TypeAttr,
     name: String,
+    bits: u8,
     values: Vec<u64>,
 }
 
@@ -446,14 +595,215 @@ impl ResourceType {
         );
         values.sort();
         values.dedup();
+        // The last one in the inheritance path is the underlying integer type
+        let bits = arg_type_bits(path.last().unwrap());
         Self {
             attr,
             name: resource.name.name.clone(),
+            bits,
             values,
         }
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct VmaType {
+    attr: TypeAttr,
+    /// Range of the number of pages
+    pages: Option<(u64, u64)>,
+}
+
+impl VmaType {
+    fn from_argument(argument: &Argument) -> Self {
+        let attr = TypeAttr::from_opts(&argument.opts);
+        // Either `vma[N]` or `vma[N:M]`
+        let pages =
+            find_range(&argument.opts).or_else(|| find_int_value(&argument.opts).map(|n| (n, n)));
+        Self { attr, pages }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConstType {
+    attr: TypeAttr,
+    bits: u8,
+    /// Number of bits for bitfields, e.g. 4 for `const[0, int32:4]`
+    bitfield_len: Option<u8>,
+    value: u64,
+}
+
+impl ConstType {
+    fn from_argument(argument: &Argument, ctx: &Parsed) -> Self {
+        let attr = TypeAttr::from_opts(&argument.opts);
+        let bits = subarg_bits(&argument.opts);
+        let value = find_values(&argument.opts)
+            .first()
+            .and_then(|val| value_to_u64_flatten(val, ctx))
+            .or_else(|| ident_to_u64(find_ident(&argument.opts)?, ctx))
+            .expect("No value for const type");
+        let bitfield_len = subarg_bitfield_len(&argument.opts, bits);
+        Self {
+            attr,
+            bits,
+            bitfield_len,
+            value,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcType {
+    attr: TypeAttr,
+    bits: u8,
+    /// Number of bits for bitfields, e.g. 4 for `proc[0, 4, int32:4]`
+    bitfield_len: Option<u8>,
+    start: u64,
+    per_proc: u64,
+}
+
+impl ProcType {
+    fn from_argument(argument: &Argument) -> Self {
+        let attr = TypeAttr::from_opts(&argument.opts);
+        let bits = subarg_bits(&argument.opts);
+        let values = find_values(&argument.opts);
+        let [start, per_proc] = values[..] else {
+            panic!("Proc type needs a start value and the number of values per process");
+        };
+        let (start, per_proc) = (value_to_u64(start), value_to_u64(per_proc));
+        assert!(
+            per_proc > 0,
+            "Proc type needs at least one value per process"
+        );
+        Self {
+            attr,
+            bits,
+            bitfield_len: subarg_bitfield_len(&argument.opts, bits),
+            start,
+            per_proc,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsumKind {
+    /// Internet checksum (RFC 1071)
+    Inet,
+    /// Internet checksum with a pseudo header of the given protocol
+    Pseudo(u64),
+}
+
+#[derive(Debug, Clone)]
+pub struct CsumType {
+    attr: TypeAttr,
+    bits: u8,
+    /// Name of the sibling field to compute the checksum of, or `parent`
+    /// for the struct containing this field
+    target: String,
+    kind: CsumKind,
+}
+
+impl CsumType {
+    fn from_argument(argument: &Argument, ctx: &Parsed) -> Self {
+        let attr = TypeAttr::from_opts(&argument.opts);
+        let bits = subarg_bits(&argument.opts);
+        let idents = find_idents(&argument.opts);
+        let [target, kind, ..] = idents[..] else {
+            panic!("Csum type needs a target field and a checksum kind");
+        };
+        let kind = match kind.name.as_str() {
+            "inet" => CsumKind::Inet,
+            "pseudo" => {
+                let proto = idents
+                    .get(2)
+                    .and_then(|ident| ident_to_u64(ident, ctx))
+                    .or_else(|| find_values(&argument.opts).first().map(|v| value_to_u64(v)))
+                    .expect("No protocol for pseudo header checksum");
+                CsumKind::Pseudo(proto)
+            }
+            other => panic!("Unknown checksum kind: {other}"),
+        };
+        Self {
+            attr,
+            bits,
+            target: target.name.clone(),
+            kind,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LenKind {
+    /// Number of elements for arrays, number of bytes otherwise
+    Len,
+    /// Number of bytes
+    Bytesize,
+    /// Number of bits
+    Bitsize,
+}
+
+#[derive(Debug, Clone)]
+pub struct LenType {
+    attr: TypeAttr,
+    bits: u8,
+    /// Number of bits for bitfields, e.g. 4 for `len[f, int32:4]`
+    bitfield_len: Option<u8>,
+    /// Name of the sibling field to compute the length of, or `parent`
+    /// for the struct containing this field
+    target: String,
+    kind: LenKind,
+}
+
+impl LenType {
+    fn from_argument(argument: &Argument) -> Self {
+        let attr = TypeAttr::from_opts(&argument.opts);
+        let bits = subarg_bits(&argument.opts);
+        let target = find_idents(&argument.opts)
+            .first()
+            .map(|ident| ident.name.clone())
+            .expect("No target field for length type");
+        let kind = match argument.argtype {
+            ArgType::Len => LenKind::Len,
+            ArgType::Bytesize => LenKind::Bytesize,
+            ArgType::Bitsize => LenKind::Bitsize,
+            _ => unreachable!("Invalid argument type for length"),
+        };
+        Self {
+            attr,
+            bits,
+            bitfield_len: subarg_bitfield_len(&argument.opts, bits),
+            target,
+            kind,
+        }
+    }
+}
+
+/// Size of an integer type in bits.
+fn int_bits(argument: &Argument) -> u8 {
+    arg_type_bits(argument.arg_type())
+}
+
+/// Size of an integer argument type in bits.
+fn arg_type_bits(arg_type: &ArgType) -> u8 {
+    let bits = match arg_type {
+        // `ARCH` may be shared by targets with different pointer widths
+        ArgType::Intptr => (PTR_SIZE * 8) as u8,
+        ArgType::Int8 | ArgType::Int16 | ArgType::Int32 | ArgType::Int64 => {
+            (arg_type.evaluate_size(&ARCH).unwrap() * 8) as u8
+        }
+        _ => unreachable!("Invalid argument type for integer"),
+    };
+    assert!(bits > 0, "Integer size has to be positive");
+    bits
+}
+
+/// Size in bits of the underlying integer type given as a type option,
+/// e.g. `int32` in `const[0, int32]`. Defaults to `intptr` if not given.
+fn subarg_bits(arg_opts: &[ArgOpt]) -> u8 {
+    ArgOpt::get_subarg(arg_opts)
+        .map(int_bits)
+        .unwrap_or((PTR_SIZE * 8) as u8)
+}
+
 fn find_dir(arg_opts: &[ArgOpt]) -> Direction {
     arg_opts
         .iter()
@@ -471,6 +821,26 @@ fn find_ident<'a>(arg_opts: &'a [ArgOpt]) -> Option<&'a Identifier> {
     })
 }
 
+fn find_idents(arg_opts: &[ArgOpt]) -> Vec<&Identifier> {
+    arg_opts
+        .iter()
+        .filter_map(|opt| match opt {
+            ArgOpt::Ident(ident) | ArgOpt::Value(Value::Ident(ident)) => Some(ident),
+            _ => None,
+        })
+        .collect()
+}
+
+fn find_values(arg_opts: &[ArgOpt]) -> Vec<&Value> {
+    arg_opts
+        .iter()
+        .filter_map(|opt| match opt {
+            ArgOpt::Value(val) => Some(val),
+            _ => None,
+        })
+        .collect()
+}
+
 fn find_range(arg_opts: &[ArgOpt]) -> Option<(u64, u64)> {
     arg_opts.iter().find_map(|opt| match opt {
         ArgOpt::Range(begin, end, _step) => Some((value_to_u64(begin), value_to_u64(end))),
@@ -485,6 +855,32 @@ fn find_length(arg_opts: &[ArgOpt]) -> Option<(u64, u64)> {
     })
 }
 
+/// Number of bits for bitfields, e.g. 3 for `int32:3`, which must fit in `bits`.
+fn find_bitfield_len(arg_opts: &[ArgOpt], bits: u8) -> Option<u8> {
+    let len = arg_opts.iter().find_map(|opt| match opt {
+        ArgOpt::Bits(val) => Some(value_to_u64(val) as u8),
+        _ => None,
+    })?;
+    assert!(
+        0 < len && len <= bits,
+        "Bitfield length {len} does not fit in {bits} bits"
+    );
+    Some(len)
+}
+
+/// Number of bits for bitfields of the underlying integer type given as a type option,
+/// e.g. 4 for `int32:4` in `flags[f, int32:4]`.
+fn subarg_bitfield_len(arg_opts: &[ArgOpt], bits: u8) -> Option<u8> {
+    ArgOpt::get_subarg(arg_opts).and_then(|subarg| find_bitfield_len(&subarg.opts, bits))
+}
+
+fn find_condition(arg_opts: &[ArgOpt]) -> Option<&str> {
+    arg_opts.iter().find_map(|opt| match opt {
+        ArgOpt::If(expr) => Some(expr.as_str()),
+        _ => None,
+    })
+}
+
 fn find_string_value(arg_opts: &[ArgOpt]) -> Option<String> {
     arg_opts.iter().find_map(|opt| match opt {
         // Don't use `value_to_string` here because we don't need to panic
@@ -494,6 +890,13 @@ fn find_string_value(arg_opts: &[ArgOpt]) -> Option<String> {
     })
 }
 
+fn find_int_value(arg_opts: &[ArgOpt]) -> Option<u64> {
+    arg_opts.iter().find_map(|opt| match opt {
+        ArgOpt::Value(Value::Int(val)) => Some(*val as u64),
+        _ => None,
+    })
+}
+
 fn find_ident_value(arg_opts: &[ArgOpt]) -> Option<&Identifier> {
     arg_opts.iter().find_map(|opt| match opt {
         ArgOpt::Value(Value::Ident(ident)) => Some(ident),
@@ -511,15 +914,22 @@ fn value_to_u64(value: &Value) -> u64 {
 fn value_to_u64_flatten(value: &Value, ctx: &Parsed) -> Option<u64> {
     match value {
         Value::Int(val) => Some(*val as u64),
-        Value::Ident(ident) => ctx
-            .consts()
-            .consts()
-            .find(|c| c.name() == ident.name)
-            .and_then(|c| c.as_uint().ok()),
+        Value::Ident(ident) => const_to_u64(&ident.name, ctx),
         _ => None,
     }
 }
 
+fn const_to_u64(name: &str, ctx: &Parsed) -> Option<u64> {
+    ctx.consts()
+        .consts()
+        .find(|c| c.name() == name)
+        .and_then(|c| c.as_uint().ok())
+}
+
+fn ident_to_u64(ident: &Identifier, ctx: &Parsed) -> Option<u64> {
+    value_to_u64_flatten(&Value::Ident(ident.clone()), ctx)
+}
+
 fn value_to_string(value: &Value) -> String {
     match value {
         Value::String(val) => val.clone(),
@@ -1,25 +1,22 @@
 use std::num::NonZeroUsize;
 
-use libafl::{
-    inputs::{BytesInput, HasMutatorBytes},
-    mutators::{havoc_mutations_no_crossover, MutationResult, MutatorsTuple},
-    state::{HasRand, NopState},
-};
 use libafl_bolts::{nonzero, rands::Rand};
 
 use enum_dispatch::enum_dispatch;
 use enum_downcast::EnumDowncast;
-use libafl_bolts::HasLen;
 
-use super::generation::{rand_filename_length, MAX_BUFFER_LENGTH};
+use super::bytes::ByteMutator;
+use super::generation::{rand_filename_length, rand_int, MAX_BUFFER_LENGTH};
+use super::layout::update_computed_fields;
 use super::{
-    ArrayType, ByteBuffer, Field, FilenameBuffer, FlagType, IntType, PointerType, ResourceType,
-    StringBuffer, StructType, Type, UnionType,
+    ArrayType, ByteBuffer, ConstType, CsumType, Field, FilenameBuffer, FlagType, IntType, LenType,
+    PointerType, ProcType, ResourceType, StringBuffer, StructType, Type, UnionType, VmaType,
 };
 use crate::generator::generate_arg;
 use crate::program::{
-    call::{Arg, Call, ConstArg, DataArg, PointerArg},
+    call::{Arg, Call, ConstArg, DataArg, GroupArg, PointerArg, UnionArg},
     context::Context,
+    metadata::truncate_to_bits,
 };
 use crate::utility::*;
 
@@ -32,7 +29,14 @@ pub trait MutateArg {
 
 impl MutateArg for Field {
     fn mutate<R: Rand>(&self, rand: &mut R, ctx: &mut Context, arg: &mut Arg) -> Vec<Call> {
-        self.ty.mutate(rand, ctx, arg)
+        match self.unwrap_arg_mut(arg) {
+            Some(arg) => {
+                ctx.narrow_donors(|donor| self.unwrap_arg(donor));
+                self.ty.mutate(rand, ctx, arg)
+            }
+            // Absent conditional fields are decided by their siblings
+            None => vec![],
+        }
     }
 }
 
@@ -40,7 +44,7 @@ impl MutateArg for IntType {
     fn mutate<R: Rand>(&self, rand: &mut R, _ctx: &mut Context, arg: &mut Arg) -> Vec<Call> {
         let arg = arg.enum_downcast_mut::<ConstArg>().unwrap();
 
-        arg.0 = if binary(rand) {
+        let val = if binary(rand) {
             self.generate_impl(rand)
         } else {
             // Refactored, but the probability is the same as syzkaller implementation
@@ -55,6 +59,7 @@ impl MutateArg for IntType {
                 }
             }
         };
+        arg.0 = truncate_to_bits(val, self.bits);
 
         vec![]
     }
@@ -65,7 +70,7 @@ impl MutateArg for FlagType {
         let arg = arg.enum_downcast_mut::<ConstArg>().unwrap();
 
         loop {
-            let val = self.generate_impl(rand, arg.0);
+            let val = truncate_to_bits(self.generate_impl(rand, arg.0), self.bits);
             if arg.0 != val {
                 arg.0 = val;
                 return vec![];
@@ -86,7 +91,14 @@ impl MutateArg for PointerType {
 
         let regenerate = one_of(rand, 3);
         match pointer_arg {
-            PointerArg::Data(data) if !regenerate => self.elem.mutate(rand, ctx, data), // Mutate inner
+            PointerArg::Data(data) if !regenerate => {
+                // Mutate inner
+                ctx.narrow_donors(|donor| match donor {
+                    Arg::PointerArg(PointerArg::Data(inner)) => Some(inner.as_ref()),
+                    _ => None,
+                });
+                self.elem.mutate(rand, ctx, data)
+            }
             _ => {
                 // Regenerate
                 let (new_arg, new_calls) = generate_arg(rand, ctx, &Type::Pointer(self.clone()));
@@ -110,7 +122,7 @@ impl MutateArg for StringBuffer {
                     *data = bytes;
                 } else {
                     // Mutate
-                    mutate_bytes(data, None);
+                    mutate_bytes(rand, ctx, data, None);
                 }
             }
             DataArg::Out(len) => {
@@ -130,10 +142,11 @@ impl MutateArg for FilenameBuffer {
             DataArg::In(data) => {
                 let mut bytes = self.generate_filename(rand, ctx).into_bytes();
                 bytes.truncate(MAX_BUFFER_LENGTH as usize);
+                *data = bytes;
             }
             DataArg::Out(len) => {
                 if one_of(rand, 100) {
-                    *len = rand_filename_length(rand);
+                    *len = rand_filename_length(rand, ctx.file_tree());
                 } else {
                     mutate_buffer_length(rand, len, None);
                 }
@@ -145,12 +158,12 @@ impl MutateArg for FilenameBuffer {
 }
 
 impl MutateArg for ByteBuffer {
-    fn mutate<R: Rand>(&self, rand: &mut R, _ctx: &mut Context, arg: &mut Arg) -> Vec<Call> {
+    fn mutate<R: Rand>(&self, rand: &mut R, ctx: &mut Context, arg: &mut Arg) -> Vec<Call> {
         let arg = arg.enum_downcast_mut::<DataArg>().unwrap();
 
         match arg {
             DataArg::In(data) => {
-                mutate_bytes(data, self.range);
+                mutate_bytes(rand, ctx, data, self.range);
                 assert!(data.len() as u64 <= MAX_BUFFER_LENGTH);
             }
             DataArg::Out(len) => mutate_buffer_length(rand, len, self.range),
@@ -161,14 +174,55 @@ impl MutateArg for ByteBuffer {
 }
 
 impl MutateArg for StructType {
-    fn mutate<R: Rand>(&self, _rand: &mut R, _ctx: &mut Context, _arg: &mut Arg) -> Vec<Call> {
-        todo!("StructType::mutate")
+    fn mutate<R: Rand>(&self, rand: &mut R, ctx: &mut Context, arg: &mut Arg) -> Vec<Call> {
+        let args = arg.enum_downcast_mut::<GroupArg>().unwrap().args_mut();
+
+        // Choose a random present field to mutate
+        let candidates: Vec<usize> = (0..self.fields.len())
+            .filter(|&i| self.fields[i].unwrap_arg(&args[i]).is_some())
+            .collect();
+        if candidates.is_empty() {
+            return vec![];
+        }
+        let idx = candidates[rand.below(candidates.len().try_into().unwrap())];
+        let field = &self.fields[idx];
+        ctx.narrow_donors(|donor| {
+            let donor = donor.enum_downcast_ref::<GroupArg>()?.args().get(idx)?;
+            field.unwrap_arg(donor)
+        });
+        let mut calls = field
+            .ty
+            .mutate(rand, ctx, field.unwrap_arg_mut(&mut args[idx]).unwrap());
+
+        // The mutated field may change conditions and computed fields
+        calls.extend(self.update_conditions(args, |ty| generate_arg(rand, ctx, ty)));
+        update_computed_fields(&self.fields, args, Some(self), Some(idx));
+        calls
     }
 }
 
 impl MutateArg for UnionType {
-    fn mutate<R: Rand>(&self, _rand: &mut R, _ctx: &mut Context, _arg: &mut Arg) -> Vec<Call> {
-        todo!("UnionType::mutate")
+    fn mutate<R: Rand>(&self, rand: &mut R, ctx: &mut Context, arg: &mut Arg) -> Vec<Call> {
+        let union = arg.enum_downcast_mut::<UnionArg>().unwrap();
+
+        if self.fields.len() > 1 && binary(rand) {
+            // Choose another variant
+            let mut idx = rand.below((self.fields.len() - 1).try_into().unwrap());
+            if idx >= union.index() {
+                idx += 1;
+            }
+            let (new_arg, new_calls) = generate_arg(rand, ctx, &self.fields[idx].ty);
+            *union = UnionArg::new(idx, new_arg);
+            new_calls
+        } else {
+            // Mutate the current variant
+            let idx = union.index();
+            ctx.narrow_donors(|donor| {
+                let donor = donor.enum_downcast_ref::<UnionArg>()?;
+                (donor.index() == idx).then(|| donor.arg())
+            });
+            self.fields[idx].ty.mutate(rand, ctx, union.arg_mut())
+        }
     }
 }
 
@@ -181,40 +235,89 @@ impl MutateArg for ResourceType {
     }
 }
 
-/// Mutate some bytes with mutators from [`libafl::mutators::havoc_mutations_no_crossover`].
-fn mutate_bytes(bytes: &mut Vec<u8>, range: Option<(u64, u64)>) {
-    // TODO: Maybe use `static` to avoid re-creating the mutators and state every time
-    let mut mutators = havoc_mutations_no_crossover();
-    let mut nop_state = NopState::<BytesInput>::new();
-    let mut input = BytesInput::new(bytes.to_vec());
+impl MutateArg for VmaType {
+    fn mutate<R: Rand>(&self, rand: &mut R, _ctx: &mut Context, arg: &mut Arg) -> Vec<Call> {
+        let arg = arg.enum_downcast_mut::<ConstArg>().unwrap();
+        arg.0 = self.generate_impl(rand);
+        vec![]
+    }
+}
 
-    loop {
-        let index = nop_state
-            .rand_mut()
-            .below(mutators.len().try_into().unwrap());
-        let result = mutators
-            .get_and_mutate(index.into(), &mut nop_state, &mut input)
-            .unwrap();
-        if result == MutationResult::Mutated && one_of(nop_state.rand_mut(), 3) {
-            break;
-        }
+impl MutateArg for ConstType {
+    fn mutate<R: Rand>(&self, rand: &mut R, _ctx: &mut Context, arg: &mut Arg) -> Vec<Call> {
+        let arg = arg.enum_downcast_mut::<ConstArg>().unwrap();
+        let value = truncate_to_bits(self.value, self.bits);
+
+        // Restore a broken value, or occasionally break it
+        arg.0 = if arg.0 != value || !one_of(rand, 10) {
+            value
+        } else if binary(rand) {
+            let shift = rand.below(unsafe { NonZeroUsize::new_unchecked(self.bits as usize) });
+            value ^ (1 << shift)
+        } else {
+            truncate_to_bits(rand.next(), self.bits)
+        };
+
+        vec![]
     }
+}
 
-    // Resize if it can not fitted into the range
-    if let Some((min, max)) = range {
-        let len = input.len() as u64;
-        if len < min {
-            input.resize(min as usize, 0);
-        } else if len > max {
-            input.resize(max as usize, 0);
+impl MutateArg for ProcType {
+    fn mutate<R: Rand>(&self, rand: &mut R, _ctx: &mut Context, arg: &mut Arg) -> Vec<Call> {
+        let arg = arg.enum_downcast_mut::<ConstArg>().unwrap();
+
+        // Only one value available, nothing to mutate
+        if self.per_proc == 1 {
+            arg.0 = self.generate_impl(rand);
+            return vec![];
         }
+        loop {
+            let val = self.generate_impl(rand);
+            if arg.0 != val {
+                arg.0 = val;
+                return vec![];
+            }
+        }
+    }
+}
+
+impl MutateArg for LenType {
+    // Ref: syzkaller/prog/mutation.go
+    fn mutate<R: Rand>(&self, rand: &mut R, _ctx: &mut Context, arg: &mut Arg) -> Vec<Call> {
+        let arg = arg.enum_downcast_mut::<ConstArg>().unwrap();
+
+        // Give wrong lengths to the kernel, the correct ones are restored
+        // whenever another argument of the enclosing call is mutated
+        let val = if n_out_of(rand, 1, 3) {
+            arg.0.wrapping_add(rand.below(nonzero!(4)) as u64 + 1)
+        } else if binary(rand) {
+            arg.0.wrapping_sub(rand.below(nonzero!(4)) as u64 + 1)
+        } else {
+            rand_int(rand, self.bits)
+        };
+        arg.0 = truncate_to_bits(val, self.bits);
+
+        vec![]
     }
-    if input.len() > MAX_BUFFER_LENGTH as usize {
-        input.resize(MAX_BUFFER_LENGTH as usize, 0);
+}
+
+impl MutateArg for CsumType {
+    fn mutate<R: Rand>(&self, _rand: &mut R, _ctx: &mut Context, _arg: &mut Arg) -> Vec<Call> {
+        // Checksums are computed by the enclosing struct
+        vec![]
     }
+}
 
-    bytes.resize(input.len(), 0);
-    bytes.copy_from_slice(input.bytes());
+/// Mutate some bytes with [`ByteMutator`], using tokens from the dictionary and
+/// buffers from the corpus in the context.
+fn mutate_bytes<R: Rand>(
+    rand: &mut R,
+    ctx: &Context,
+    bytes: &mut Vec<u8>,
+    range: Option<(u64, u64)>,
+) {
+    let donors = ctx.donor_data();
+    ByteMutator::new(range, ctx.dictionary().tokens(), &donors).mutate(rand, bytes);
 }
 
 /// Mutate the length of a buffer.
@@ -1,30 +1,61 @@
 //! LibAFL QEMU based runner to reproduce a crash.
 
+#[cfg(not(feature = "bytes"))]
+use std::cell::RefCell;
 use std::time::Duration;
 
 #[cfg(feature = "bytes")]
 use libafl::inputs::BytesInput;
 use libafl::{
-    corpus::NopCorpus, events::SimpleEventManager, inputs::Input, monitors::SimpleMonitor,
-    schedulers::QueueScheduler, state::StdState, StdFuzzer,
+    corpus::NopCorpus, events::SimpleEventManager, executors::ExitKind, inputs::Input,
+    monitors::SimpleMonitor, schedulers::QueueScheduler, state::StdState, StdFuzzer,
 };
 use libafl_bolts::{rands::StdRand, tuples::tuple_list};
+#[cfg(not(feature = "bytes"))]
+use libafl_qemu::GuestAddr;
 use libafl_qemu::{executor::QemuExecutor, Emulator};
 
+#[cfg(not(feature = "bytes"))]
+use crate::binutils::Binutils;
 use crate::cli::ReproduceOption;
 
 #[cfg(not(feature = "bytes"))]
-use flicker::input::SyscallInput;
+use flicker::{
+    input::SyscallInput, parser::parse, program::metadata::SyscallMetadata, trace::CallTrace,
+};
 
 pub fn reproduce(opt: ReproduceOption) {
     let ReproduceOption {
         testcase,
         timeout,
+        #[cfg(not(feature = "bytes"))]
+        trace,
+        #[cfg(not(feature = "bytes"))]
+        trace_symbol,
+        #[cfg(not(feature = "bytes"))]
+        binutils_prefix,
+        #[cfg(not(feature = "bytes"))]
+        builtin,
+        #[cfg(not(feature = "bytes"))]
+        desc,
+        #[cfg(not(feature = "bytes"))]
+        r#const,
         mut args,
     } = opt;
 
     let timeout = Duration::from_secs(timeout);
 
+    // Address of the buffer in the harness, where the return values of calls are reported
+    #[cfg(not(feature = "bytes"))]
+    let trace_addr = trace.map(|harness| {
+        Binutils::new(&binutils_prefix)
+            .symbol(&harness, &trace_symbol)
+            .unwrap_or_else(|| panic!("Symbol {trace_symbol} not found in {harness:?}"))
+            as GuestAddr
+    });
+    #[cfg(not(feature = "bytes"))]
+    let trace_buffer = RefCell::new(None);
+
     // Usually qemu is initialized with `env::args().collect()`,
     // where the first argument is the path of the executable.
     // Since we directly pass arguments into the fuzzer, we add
@@ -42,9 +73,19 @@ pub fn reproduce(opt: ReproduceOption) {
         .build()
         .expect("Failed to initialize QEMU");
 
-    // The wrapped harness function, calling out to the LLVM-style harness
+    // The wrapped harness function, calling out to the LLVM-style harness,
+    // and reading the return values of calls after the run if tracing
     let mut harness = |emu: &mut Emulator<_, _, _, _, _>, state: &mut _, input: &Input| unsafe {
-        emu.run(state, input).unwrap().try_into().unwrap()
+        let exit_kind: ExitKind = emu.run(state, input).unwrap().try_into().unwrap();
+        #[cfg(not(feature = "bytes"))]
+        if let Some(addr) = trace_addr {
+            let mut buffer = vec![0; CallTrace::size(input.calls().len())];
+            match emu.qemu().read_mem(addr, &mut buffer) {
+                Ok(()) => *trace_buffer.borrow_mut() = Some(buffer),
+                Err(e) => println!("Failed to read the trace at {addr:#x}: {e:?}"),
+            }
+        }
+        exit_kind
     };
 
     // Load input and make it into a corpus
@@ -97,4 +138,14 @@ pub fn reproduce(opt: ReproduceOption) {
         Ok(kind) => println!("Execution succeeded: {:?}", kind),
         Err(e) => println!("Execution failed: {}", e),
     }
+
+    #[cfg(not(feature = "bytes"))]
+    if let Some(buffer) = trace_buffer.take() {
+        let metadata = (!desc.is_empty())
+            .then(|| SyscallMetadata::from_parsed(parse(builtin.as_deref(), &desc, &r#const)));
+        println!(
+            "Trace:\n{}",
+            CallTrace::parse(&buffer).annotate(&input, metadata.as_ref())
+        );
+    }
 }
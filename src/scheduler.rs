@@ -0,0 +1,119 @@
+//! Corpus scheduling favoring testcases with rare edges or rare syscalls.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use libafl::{
+    corpus::{Corpus, Testcase},
+    feedbacks::MapIndexesMetadata,
+    schedulers::testcase_score::{CorpusWeightTestcaseScore, TestcaseScore},
+    state::HasCorpus,
+    Error, HasMetadata,
+};
+use libafl_bolts::SerdeAny;
+use serde::{Deserialize, Serialize};
+
+use crate::input::SyscallInput;
+use crate::stats::unique_syscalls;
+
+/// Edges or syscalls covered by at most this number of corpus entries are rare.
+const RARE_THRESHOLD: u64 = 2;
+
+/// Max number of rare edges or syscalls rewarded in a testcase.
+const MAX_RARE_COUNT: usize = 8;
+
+/// Bonus to the score for each rare edge.
+const RARE_EDGE_BONUS: f64 = 0.5;
+
+/// Bonus to the score for each rare syscall.
+const RARE_SYSCALL_BONUS: f64 = 1.0;
+
+/// Number of corpus entries covering each edge and using each syscall, stored in the state.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, SerdeAny)]
+pub struct CorpusRarityMetadata {
+    edges: HashMap<usize, u64>,
+    syscalls: HashMap<u32, u64>,
+    /// Number of corpus entries counted
+    entries: usize,
+}
+
+impl CorpusRarityMetadata {
+    /// Count the edges and syscalls of all entries in the corpus.
+    pub fn from_corpus<C>(corpus: &C) -> Result<Self, Error>
+    where
+        C: Corpus<Input = SyscallInput>,
+    {
+        let mut rarity = Self::default();
+        for id in corpus.ids() {
+            let testcase = corpus.get(id)?.borrow();
+            if let Ok(indexes) = testcase.metadata::<MapIndexesMetadata>() {
+                for edge in &indexes.list {
+                    *rarity.edges.entry(*edge).or_default() += 1;
+                }
+            }
+            if let Some(input) = testcase.input() {
+                for nr in unique_syscalls(input) {
+                    *rarity.syscalls.entry(nr).or_default() += 1;
+                }
+            }
+            rarity.entries += 1;
+        }
+        Ok(rarity)
+    }
+
+    pub fn entries(&self) -> usize {
+        self.entries
+    }
+
+    pub fn edge_count(&self, edge: usize) -> u64 {
+        self.edges.get(&edge).copied().unwrap_or(0)
+    }
+
+    pub fn syscall_count(&self, nr: u32) -> u64 {
+        self.syscalls.get(&nr).copied().unwrap_or(0)
+    }
+}
+
+/// Weighted score based on [`CorpusWeightTestcaseScore`], with bonus for rare edges and
+/// syscalls according to [`CorpusRarityMetadata`].
+#[derive(Debug, Clone)]
+pub struct RareTestcaseScore<S> {
+    phantom: PhantomData<S>,
+}
+
+impl<S> TestcaseScore<S> for RareTestcaseScore<S>
+where
+    S: HasCorpus<Corpus: Corpus<Input = SyscallInput>> + HasMetadata,
+{
+    fn compute(state: &S, entry: &mut Testcase<SyscallInput>) -> Result<f64, Error> {
+        let score = CorpusWeightTestcaseScore::<S>::compute(state, entry)?;
+        let Ok(rarity) = state.metadata::<CorpusRarityMetadata>() else {
+            return Ok(score);
+        };
+
+        let rare_edges = entry
+            .metadata::<MapIndexesMetadata>()
+            .map(|indexes| {
+                indexes
+                    .list
+                    .iter()
+                    .filter(|edge| rarity.edge_count(**edge) <= RARE_THRESHOLD)
+                    .count()
+            })
+            .unwrap_or(0);
+        let rare_syscalls = entry
+            .input()
+            .as_ref()
+            .map(|input| {
+                unique_syscalls(input)
+                    .into_iter()
+                    .filter(|nr| rarity.syscall_count(*nr) <= RARE_THRESHOLD)
+                    .count()
+            })
+            .unwrap_or(0);
+
+        let bonus = RARE_EDGE_BONUS * rare_edges.min(MAX_RARE_COUNT) as f64
+            + RARE_SYSCALL_BONUS * rare_syscalls.min(MAX_RARE_COUNT) as f64;
+        Ok(score * (1.0 + bonus))
+    }
+}
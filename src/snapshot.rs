@@ -0,0 +1,75 @@
+//! Snapshot management of the guest.
+//!
+//! The harness takes a snapshot with the `start` command right before reading the input,
+//! and the snapshot is restored with the `end` command after each execution. Fast snapshots
+//! only restore the memory and the tracked devices, so some kernel state may still leak
+//! between executions. Full QEMU snapshots (`savevm` / `loadvm`) restore the whole machine,
+//! but are much slower, so they can be restored only every N executions.
+
+use std::collections::HashMap;
+
+use libafl_qemu::{
+    emu::{FastSnapshotManager, QemuSnapshotManager},
+    IsSnapshotManager, Qemu, QemuSnapshotCheckResult, SnapshotId, SnapshotManagerCheckError,
+    SnapshotManagerError,
+};
+use log::debug;
+
+/// Restore fast snapshots, and full QEMU snapshots every `interval` executions.
+#[derive(Debug, Clone)]
+pub struct FlickerSnapshotManager {
+    fast: FastSnapshotManager,
+    full: QemuSnapshotManager,
+    /// Full snapshots of the fast ones
+    full_ids: HashMap<SnapshotId, SnapshotId>,
+    /// Restore a full snapshot every `interval` executions, never if 0
+    interval: u64,
+    restores: u64,
+}
+
+impl FlickerSnapshotManager {
+    pub fn new(interval: u64) -> Self {
+        Self {
+            fast: FastSnapshotManager::new(),
+            full: QemuSnapshotManager::new(true),
+            full_ids: HashMap::new(),
+            interval,
+            restores: 0,
+        }
+    }
+}
+
+impl IsSnapshotManager for FlickerSnapshotManager {
+    fn save(&mut self, qemu: Qemu) -> SnapshotId {
+        let id = self.fast.save(qemu);
+        if self.interval > 0 {
+            // Full snapshots require a disk image supporting them, e.g. qcow2
+            let full_id = self.full.save(qemu);
+            self.full_ids.insert(id, full_id);
+        }
+        debug!("[FlickerSnapshotManager::save] Saved snapshot {id:?}");
+        id
+    }
+
+    fn restore(
+        &mut self,
+        qemu: Qemu,
+        snapshot_id: &SnapshotId,
+    ) -> Result<(), SnapshotManagerError> {
+        self.restores += 1;
+        if self.interval > 0 && self.restores % self.interval == 0 {
+            if let Some(full_id) = self.full_ids.get(snapshot_id) {
+                debug!("[FlickerSnapshotManager::restore] Restoring full snapshot {full_id:?}");
+                return self.full.restore(qemu, full_id);
+            }
+        }
+        self.fast.restore(qemu, snapshot_id)
+    }
+
+    fn do_check(
+        &self,
+        reference_snapshot_id: &SnapshotId,
+    ) -> Result<QemuSnapshotCheckResult, SnapshotManagerCheckError> {
+        self.fast.do_check(reference_snapshot_id)
+    }
+}
@@ -0,0 +1,345 @@
+//! Custom stages of the fuzzer.
+
+use std::borrow::Cow;
+use std::fs;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use libafl::{
+    corpus::Corpus,
+    events::{Event, EventFirer},
+    fuzzer::Evaluator,
+    generators::Generator,
+    inputs::UsesInput,
+    monitors::{AggregatorOps, UserStats, UserStatsValue},
+    stages::Stage,
+    state::{HasCorpus, HasRand, State, UsesState},
+    Error, HasMetadata,
+};
+use libafl_bolts::{current_time, rands::Rand};
+use log::debug;
+
+use crate::input::SyscallInput;
+use crate::mutator::{SyscallMutatorKind, SyscallMutatorStats};
+use crate::program::metadata::SyscallMetadata;
+use crate::scheduler::CorpusRarityMetadata;
+use crate::stats::{syscall_name, SyscallStats};
+
+/// Interval between two reports of statistics.
+const REPORT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Report [`SyscallMutatorStats`] as user stats to the monitor, in the form of
+/// `mutator-<name>: <successes>/<applied>`.
+pub struct MutatorStatsStage<S> {
+    last_report: Duration,
+    phantom: PhantomData<S>,
+}
+
+impl<S> MutatorStatsStage<S> {
+    pub fn new() -> Self {
+        Self {
+            last_report: Duration::ZERO,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Default for MutatorStatsStage<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> UsesState for MutatorStatsStage<S>
+where
+    S: State,
+{
+    type State = S;
+}
+
+impl<E, EM, Z, S> Stage<E, EM, Z> for MutatorStatsStage<S>
+where
+    S: State + HasMetadata,
+    E: UsesState<State = S>,
+    EM: EventFirer<State = S>,
+    Z: UsesState<State = S>,
+{
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // Reporting is idempotent
+        Ok(true)
+    }
+
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let now = current_time();
+        if now.saturating_sub(self.last_report) < REPORT_INTERVAL {
+            return Ok(());
+        }
+        self.last_report = now;
+
+        let Ok(stats) = state.metadata::<SyscallMutatorStats>() else {
+            return Ok(());
+        };
+        let events: Vec<_> = SyscallMutatorKind::ALL
+            .iter()
+            .filter(|kind| stats.applied(**kind) > 0)
+            .map(|kind| Event::UpdateUserStats {
+                name: Cow::Owned(format!("mutator-{kind}")),
+                value: UserStats::new(
+                    UserStatsValue::Ratio(stats.successes(*kind), stats.applied(*kind)),
+                    AggregatorOps::Sum,
+                ),
+                phantom: PhantomData,
+            })
+            .collect();
+        for event in events {
+            manager.fire(state, event)?;
+        }
+        Ok(())
+    }
+}
+
+/// Report [`SyscallStats`] as user stats to the monitor, in the form of
+/// `syscall-<name>: <executions>/<corpus>/<crashes>/<avg new edges>` and
+/// `crash-<i>: <last syscall> (<calls> calls, <secs>s ago)` for recent crashes,
+/// and dump them in JSON to the file if given.
+pub struct SyscallStatsStage<S> {
+    metadata: SyscallMetadata,
+    dump: Option<PathBuf>,
+    last_report: Duration,
+    phantom: PhantomData<S>,
+}
+
+impl<S> SyscallStatsStage<S> {
+    pub fn new(metadata: SyscallMetadata, dump: Option<PathBuf>) -> Self {
+        Self {
+            metadata,
+            dump,
+            last_report: Duration::ZERO,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> UsesState for SyscallStatsStage<S>
+where
+    S: State,
+{
+    type State = S;
+}
+
+impl<E, EM, Z, S> Stage<E, EM, Z> for SyscallStatsStage<S>
+where
+    S: State + HasMetadata,
+    E: UsesState<State = S>,
+    EM: EventFirer<State = S>,
+    Z: UsesState<State = S>,
+{
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // Reporting is idempotent
+        Ok(true)
+    }
+
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let now = current_time();
+        if now.saturating_sub(self.last_report) < REPORT_INTERVAL {
+            return Ok(());
+        }
+        self.last_report = now;
+
+        let Ok(stats) = state.metadata::<SyscallStats>() else {
+            return Ok(());
+        };
+        if let Some(path) = &self.dump {
+            let mut json = stats.to_json(&self.metadata);
+            json["time"] = now.as_secs().into();
+            fs::write(path, json.to_string())?;
+        }
+        let crashes = stats
+            .recent_crashes()
+            .enumerate()
+            .map(|(i, crash)| Event::UpdateUserStats {
+                name: Cow::Owned(format!("crash-{}", i + 1)),
+                value: UserStats::new(
+                    UserStatsValue::String(Cow::Owned(format!(
+                        "{} ({} calls, {}s ago)",
+                        syscall_name(&self.metadata, crash.last),
+                        crash.calls,
+                        now.saturating_sub(crash.time).as_secs()
+                    ))),
+                    AggregatorOps::None,
+                ),
+                phantom: PhantomData,
+            });
+        let events: Vec<_> = stats
+            .iter()
+            .map(|(nr, stat)| Event::UpdateUserStats {
+                name: Cow::Owned(format!("syscall-{}", syscall_name(&self.metadata, nr))),
+                value: UserStats::new(
+                    UserStatsValue::String(Cow::Owned(format!(
+                        "{}/{}/{}/{:.1}",
+                        stat.executions,
+                        stat.corpus,
+                        stat.crashes,
+                        stat.avg_new_edges()
+                    ))),
+                    AggregatorOps::None,
+                ),
+                phantom: PhantomData,
+            })
+            .chain(crashes)
+            .collect();
+        for event in events {
+            manager.fire(state, event)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keep [`CorpusRarityMetadata`] up to date, recounting when the corpus grows.
+pub struct CorpusRarityStage<S> {
+    phantom: PhantomData<S>,
+}
+
+impl<S> CorpusRarityStage<S> {
+    pub fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<S> Default for CorpusRarityStage<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> UsesState for CorpusRarityStage<S>
+where
+    S: State,
+{
+    type State = S;
+}
+
+impl<E, EM, Z, S> Stage<E, EM, Z> for CorpusRarityStage<S>
+where
+    S: State + HasMetadata + HasCorpus<Corpus: Corpus<Input = SyscallInput>>,
+    E: UsesState<State = S>,
+    EM: UsesState<State = S>,
+    Z: UsesState<State = S>,
+{
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        Ok(true)
+    }
+
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        _manager: &mut EM,
+    ) -> Result<(), Error> {
+        let counted = state
+            .metadata::<CorpusRarityMetadata>()
+            .map_or(0, |rarity| rarity.entries());
+        if counted == state.corpus().count() {
+            return Ok(());
+        }
+        let rarity = CorpusRarityMetadata::from_corpus(state.corpus())?;
+        state.add_metadata(rarity);
+        Ok(())
+    }
+}
+
+/// Generate and evaluate a brand-new input with the given probability, like syzkaller,
+/// to avoid getting stuck on a small corpus. The probability is raised to `1 / (n + 1)`
+/// when there are only `n` entries in the corpus.
+pub struct GenerationStage<G, S> {
+    generator: G,
+    probability: f64,
+    phantom: PhantomData<S>,
+}
+
+impl<G, S> GenerationStage<G, S> {
+    pub fn new(generator: G, probability: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&probability),
+            "Generation probability {probability} not in [0, 1]"
+        );
+        Self {
+            generator,
+            probability,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<G, S> UsesState for GenerationStage<G, S>
+where
+    S: State,
+{
+    type State = S;
+}
+
+impl<E, EM, Z, G, S> Stage<E, EM, Z> for GenerationStage<G, S>
+where
+    S: State + HasRand + HasCorpus,
+    G: Generator<<S as UsesInput>::Input, S>,
+    E: UsesState<State = S>,
+    EM: UsesState<State = S>,
+    Z: Evaluator<E, EM, State = S>,
+{
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // Generation is not resumed after a crash
+        Ok(true)
+    }
+
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let probability = self
+            .probability
+            .max(1.0 / (state.corpus().count() + 1) as f64);
+        if state.rand_mut().next_float() >= probability {
+            return Ok(());
+        }
+
+        let input = self.generator.generate(state)?;
+        let (result, _) = fuzzer.evaluate_input(state, executor, manager, input)?;
+        debug!("[GenerationStage::perform] Evaluated a generated input: {result:?}");
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+//! Per-syscall statistics of the fuzzer.
+
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::time::Duration;
+
+use libafl_bolts::{current_time, SerdeAny};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::input::SyscallInput;
+use crate::program::metadata::SyscallMetadata;
+
+/// Statistics of a syscall.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct SyscallStat {
+    /// Number of executed inputs containing the syscall
+    pub executions: u64,
+    /// Number of corpus entries containing the syscall
+    pub corpus: u64,
+    /// Number of solutions containing the syscall
+    pub crashes: u64,
+    /// Number of new edges found by corpus entries containing the syscall
+    pub new_edges: u64,
+}
+
+impl SyscallStat {
+    /// Average number of new edges found by corpus entries containing the syscall.
+    pub fn avg_new_edges(&self) -> f64 {
+        if self.corpus == 0 {
+            0.0
+        } else {
+            self.new_edges as f64 / self.corpus as f64
+        }
+    }
+}
+
+/// Number of recent crashes kept in [`SyscallStats`].
+const RECENT_CRASHES: usize = 5;
+
+/// A crash found recently, titled by its last syscall.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RecentCrash {
+    /// Number of the last syscall, which is likely to trigger the crash
+    pub last: u32,
+    /// Number of syscalls in the input
+    pub calls: usize,
+    /// Time when the crash is found
+    pub time: Duration,
+}
+
+/// Statistics of syscalls by their numbers, stored in the state.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, SerdeAny)]
+pub struct SyscallStats {
+    stats: BTreeMap<u32, SyscallStat>,
+    /// Edges covered by the corpus entries counted
+    edges: HashSet<usize>,
+    /// Most recent crashes, the latest first
+    recent_crashes: VecDeque<RecentCrash>,
+}
+
+impl SyscallStats {
+    pub fn get(&self, nr: u32) -> SyscallStat {
+        self.stats.get(&nr).copied().unwrap_or_default()
+    }
+
+    /// Syscalls ever executed and their statistics, ordered by numbers.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &SyscallStat)> {
+        self.stats.iter().map(|(nr, stat)| (*nr, stat))
+    }
+
+    pub fn record_execution(&mut self, input: &SyscallInput) {
+        for nr in unique_syscalls(input) {
+            self.stats.entry(nr).or_default().executions += 1;
+        }
+    }
+
+    /// Most recent crashes, the latest first.
+    pub fn recent_crashes(&self) -> impl Iterator<Item = &RecentCrash> {
+        self.recent_crashes.iter()
+    }
+
+    pub fn record_crash(&mut self, input: &SyscallInput) {
+        for nr in unique_syscalls(input) {
+            self.stats.entry(nr).or_default().crashes += 1;
+        }
+        if let Some(last) = input.calls().last() {
+            self.recent_crashes.push_front(RecentCrash {
+                last: last.number(),
+                calls: input.calls().len(),
+                time: current_time(),
+            });
+            self.recent_crashes.truncate(RECENT_CRASHES);
+        }
+    }
+
+    /// Record a new corpus entry covering the edges, crediting the edges not covered
+    /// before to all its syscalls.
+    pub fn record_corpus_entry(&mut self, input: &SyscallInput, edges: &[usize]) {
+        let new_edges = edges
+            .iter()
+            .filter(|edge| self.edges.insert(**edge))
+            .count() as u64;
+        for nr in unique_syscalls(input) {
+            let stat = self.stats.entry(nr).or_default();
+            stat.corpus += 1;
+            stat.new_edges += new_edges;
+        }
+    }
+
+    /// Statistics in JSON, with the names of syscalls.
+    pub fn to_json(&self, metadata: &SyscallMetadata) -> Value {
+        let syscalls: Vec<Value> = self
+            .iter()
+            .map(|(nr, stat)| {
+                json!({
+                    "nr": nr,
+                    "name": syscall_name(metadata, nr),
+                    "executions": stat.executions,
+                    "corpus": stat.corpus,
+                    "crashes": stat.crashes,
+                    "new_edges": stat.new_edges,
+                    "avg_new_edges": stat.avg_new_edges(),
+                })
+            })
+            .collect();
+        let recent_crashes: Vec<Value> = self
+            .recent_crashes()
+            .map(|crash| {
+                json!({
+                    "last": syscall_name(metadata, crash.last),
+                    "calls": crash.calls,
+                    "time": crash.time.as_secs(),
+                })
+            })
+            .collect();
+        json!({ "syscalls": syscalls, "recent_crashes": recent_crashes })
+    }
+}
+
+/// Name of the syscall without the variant, e.g. `openat` for `openat$dir`.
+pub fn syscall_name(metadata: &SyscallMetadata, nr: u32) -> String {
+    metadata
+        .find_number(nr)
+        .map(|syscall| syscall.name().split('$').next().unwrap().to_string())
+        .unwrap_or_else(|| format!("syscall_{nr}"))
+}
+
+/// Numbers of the syscalls in the input, without duplicates.
+pub fn unique_syscalls(input: &SyscallInput) -> Vec<u32> {
+    let mut numbers: Vec<u32> = input.calls().iter().map(|c| c.number()).collect();
+    numbers.sort_unstable();
+    numbers.dedup();
+    numbers
+}
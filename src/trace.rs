@@ -0,0 +1,194 @@
+//! Return values of the calls reported by the harness, and the annotated program.
+//!
+//! The harness reports the results in a static buffer named by `--trace-symbol`, laid out as
+//! a `u32` number of returned calls followed by an `i64` return value for each call, both in
+//! the byte order of the target. The values are 64-bit on every target, so 32-bit harnesses
+//! have to sign-extend the return values. The number should be reset to 0 before the first
+//! call, and increased after each call returns, so that the call causing a crash can be found.
+//!
+//! Following Linux, only return values in `-4095..=-1` are errors.
+
+use std::fmt::Write as _;
+
+use uuid::Uuid;
+
+use crate::input::SyscallInput;
+use crate::program::call::{Arg, Call, DataArg, PointerArg, ResultArg};
+use crate::program::metadata::{SyscallMetadata, ENDIAN};
+use crate::stats::syscall_name;
+
+/// Max bytes of data shown for each argument.
+const MAX_DATA_LEN: usize = 32;
+
+/// Max errno, return values in `-MAX_ERRNO..=-1` are errors.
+const MAX_ERRNO: i64 = 4095;
+
+/// Return values of the calls, in order of execution.
+#[derive(Debug, Clone)]
+pub struct CallTrace {
+    results: Vec<i64>,
+}
+
+impl CallTrace {
+    /// Size of the buffer with the results of the given number of calls.
+    pub fn size(calls: usize) -> usize {
+        4 + 8 * calls
+    }
+
+    /// Parse the results in the buffer, ignoring the ones beyond it.
+    pub fn parse(bytes: &[u8]) -> Self {
+        let count = ENDIAN.decode(&bytes[..4]) as usize;
+        let results = bytes[4..]
+            .chunks_exact(8)
+            .take(count)
+            .map(|result| ENDIAN.decode(result) as i64)
+            .collect();
+        Self { results }
+    }
+
+    /// The program with the return value of each call, marking failed calls with their errors,
+    /// and the first call not returned.
+    pub fn annotate(&self, input: &SyscallInput, metadata: Option<&SyscallMetadata>) -> String {
+        let mut out = String::new();
+        for (i, call) in input.calls().iter().enumerate() {
+            let line = format_call(input, call, metadata);
+            match self.results.get(i) {
+                Some(ret) if is_error(*ret) => {
+                    let errno = ret.unsigned_abs() as usize;
+                    let name = ERRNO_NAMES.get(errno).copied().unwrap_or("?");
+                    writeln!(out, "{line} = {ret} ({name})    <- failed").unwrap()
+                }
+                Some(ret) => writeln!(out, "{line} = {ret:#x}").unwrap(),
+                None if i == self.results.len() => {
+                    writeln!(out, "{line}    <- not returned").unwrap()
+                }
+                None => writeln!(out, "{line}    <- not executed").unwrap(),
+            }
+        }
+        let failed = self.results.iter().filter(|ret| is_error(**ret)).count();
+        write!(
+            out,
+            "{}/{} calls returned, {failed} failed",
+            self.results.len(),
+            input.calls().len()
+        )
+        .unwrap();
+        out
+    }
+}
+
+fn is_error(ret: i64) -> bool {
+    (-MAX_ERRNO..=-1).contains(&ret)
+}
+
+/// Format the call like `r0 = openat(0xffffff9c, &"./file0\x00", 0x42)`.
+pub(crate) fn format_call(
+    input: &SyscallInput,
+    call: &Call,
+    metadata: Option<&SyscallMetadata>,
+) -> String {
+    let name = metadata.map_or_else(
+        || format!("syscall_{}", call.number()),
+        |metadata| syscall_name(metadata, call.number()),
+    );
+    let args: Vec<String> = call
+        .args()
+        .iter()
+        .map(|arg| format_arg(input, arg))
+        .collect();
+    let call_text = format!("{name}({})", args.join(", "));
+    match call.result() {
+        Some(id) => format!("{} = {call_text}", result_name(input, id)),
+        None => call_text,
+    }
+}
+
+fn format_arg(input: &SyscallInput, arg: &Arg) -> String {
+    match arg {
+        Arg::ConstArg(value) => format!("{:#x}", value.0),
+        Arg::PointerArg(PointerArg::Addr(addr)) => format!("{addr:#x}"),
+        Arg::PointerArg(PointerArg::Data(data)) => format!("&{}", format_arg(input, data)),
+        Arg::DataArg(DataArg::In(bytes)) => format_data(bytes),
+        Arg::DataArg(DataArg::Out(size)) => format!("out[{size}]"),
+        Arg::GroupArg(group) => {
+            let args: Vec<String> = group
+                .args()
+                .iter()
+                .map(|arg| format_arg(input, arg))
+                .collect();
+            format!("{{{}}}", args.join(", "))
+        }
+        Arg::UnionArg(union) => format!("@{}={}", union.index(), format_arg(input, union.arg())),
+        Arg::ResultArg(ResultArg::Ref(id)) => result_name(input, *id),
+        Arg::ResultArg(ResultArg::Literal(literal)) => format!("{literal:#x}"),
+    }
+}
+
+/// Data as an escaped string, truncated if too long.
+fn format_data(bytes: &[u8]) -> String {
+    let text: String = bytes
+        .iter()
+        .take(MAX_DATA_LEN)
+        .flat_map(|byte| std::ascii::escape_default(*byte))
+        .map(char::from)
+        .collect();
+    if bytes.len() > MAX_DATA_LEN {
+        format!("\"{text}\"...[{}]", bytes.len())
+    } else {
+        format!("\"{text}\"")
+    }
+}
+
+/// Name of the result by the index of the call producing it, e.g. `r0`.
+fn result_name(input: &SyscallInput, id: Uuid) -> String {
+    input
+        .calls()
+        .iter()
+        .position(|call| call.result() == Some(id))
+        .map_or_else(|| "r?".to_string(), |i| format!("r{i}"))
+}
+
+/// Names of the common errno values, following Linux.
+const ERRNO_NAMES: [&str; 41] = [
+    "",
+    "EPERM",
+    "ENOENT",
+    "ESRCH",
+    "EINTR",
+    "EIO",
+    "ENXIO",
+    "E2BIG",
+    "ENOEXEC",
+    "EBADF",
+    "ECHILD",
+    "EAGAIN",
+    "ENOMEM",
+    "EACCES",
+    "EFAULT",
+    "ENOTBLK",
+    "EBUSY",
+    "EEXIST",
+    "EXDEV",
+    "ENODEV",
+    "ENOTDIR",
+    "EISDIR",
+    "EINVAL",
+    "ENFILE",
+    "EMFILE",
+    "ENOTTY",
+    "ETXTBSY",
+    "EFBIG",
+    "ENOSPC",
+    "ESPIPE",
+    "EROFS",
+    "EMLINK",
+    "EPIPE",
+    "EDOM",
+    "ERANGE",
+    "EDEADLK",
+    "ENAMETOOLONG",
+    "ENOLCK",
+    "ENOSYS",
+    "ENOTEMPTY",
+    "ELOOP",
+];